@@ -0,0 +1,286 @@
+use std::time::Duration;
+use device_query::{Keycode, MouseButton};
+use enigo::Settings;
+use crate::macros::{KeyAction, MacroAction, MacroMetadata, MouseButtonAction, MouseMoveAction, UserAction, DEFAULT_POLL_INTERVAL};
+use crate::{Macro, MouseMode, RemapTable};
+use crate::utils;
+
+/// The time between typing one character and the next in `type_text`.
+const TYPING_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A builder for configuring a `Macro`.
+///
+/// `Macro::new` is equivalent to building with the defaults,
+/// except that it connects to the input system straight away.
+///
+/// Actions can also be added to the macro before it's built,
+/// so that macros can be created without recording them.
+#[derive(Debug, Clone)]
+pub struct MacroBuilder {
+    settings: Settings,
+    poll_interval: Duration,
+    mouse_mode: MouseMode,
+    remap: RemapTable,
+    display_scale: Option<f64>,
+    actions: Vec<MacroAction>
+}
+
+impl Default for MacroBuilder {
+    fn default() -> Self {
+        MacroBuilder {
+            settings: Settings::default(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            mouse_mode: MouseMode::default(),
+            remap: RemapTable::new(),
+            display_scale: None,
+            actions: Vec::new()
+        }
+    }
+}
+
+impl MacroBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the settings used to connect to the input system.
+    pub fn enigo_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Sets the interval between device polls while recording.
+    ///
+    /// See `Macro::record_with_interval` for the tradeoffs involved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "polling interval must be non-zero");
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets how mouse movements are recorded.
+    ///
+    /// Macros which are loaded keep the mode they were recorded with.
+    pub fn mouse_mode(mut self, mode: MouseMode) -> Self {
+        self.mouse_mode = mode;
+        self
+    }
+
+    /// Sets the table used to look up keys during playback.
+    pub fn remap(mut self, remap: RemapTable) -> Self {
+        self.remap = remap;
+        self
+    }
+
+    /// Sets the scale factor of the display, for when it can't be detected.
+    ///
+    /// The scale factor is stored in macros when they're recorded. When
+    /// a macro using `MouseMode::Relative` is played back at a different
+    /// scale factor than it was recorded at, its moves are scaled to cover
+    /// the same distance on screen. Absolute moves aren't scaled, and
+    /// nothing is scaled if either scale factor is unknown.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scale` is not a positive, finite number.
+    pub fn display_scale(mut self, scale: f64) -> Self {
+        assert!(scale > 0.0 && scale.is_finite(), "display scale must be positive, got {}", scale);
+        self.display_scale = Some(scale);
+        self
+    }
+
+    /// Adds a key press or release at the given offset.
+    pub fn push_key(self, offset: Duration, key: Keycode, pressed: bool) -> Self {
+        let key = utils::to_string(&key);
+        self.push(offset, UserAction::Key(KeyAction { key, pressed }))
+    }
+
+    /// Adds a mouse button press or release at the given offset.
+    ///
+    /// Buttons are numbered the way `device_query` reports them.
+    pub fn push_mouse_button(self, offset: Duration, button: MouseButton, pressed: bool) -> Self {
+        self.push(offset, UserAction::MouseButton(MouseButtonAction { button, pressed }))
+    }
+
+    /// Adds a mouse move at the given offset.
+    ///
+    /// The position is interpreted according to the builder's mouse mode.
+    pub fn push_mouse_move(self, offset: Duration, x: i32, y: i32) -> Self {
+        self.push(offset, UserAction::MouseMove(MouseMoveAction { x, y }))
+    }
+
+    /// Adds the key presses needed to type `text`, after the last action.
+    ///
+    /// Characters are typed using a US keyboard layout, holding shift
+    /// where needed. Characters with no key on that layout are typed
+    /// with the key of the same name in the remap table, if there is
+    /// one, and are skipped otherwise.
+    pub fn type_text(mut self, text: &str) -> Self {
+        let step = TYPING_INTERVAL.as_micros() as u64;
+        let mut offset = self.end();
+
+        for c in text.chars() {
+            let (key, shift) = match utils::char_key(c) {
+                Some((key, shift)) => (utils::to_string(&key), shift),
+                None if self.remap.get(&c.to_string()).is_some() => (c.to_string(), false),
+                None => continue
+            };
+
+            // Press the key halfway through the character's time,
+            // so that shift is already held when it's needed.
+            let mut keys = vec![(key.clone(), true, step / 2), (key, false, step)];
+            if shift {
+                keys.insert(0, ("LShift".to_string(), true, 0));
+                keys.push(("LShift".to_string(), false, step));
+            }
+            for (key, pressed, delay) in keys {
+                self.actions.push(MacroAction {
+                    offset: offset + delay,
+                    action: UserAction::Key(KeyAction { key, pressed })
+                });
+            }
+
+            offset += step;
+        }
+
+        self
+    }
+
+    /// Adds a press of `key` after the last action, and its
+    /// release `duration` later.
+    pub fn hold_key(self, key: Keycode, duration: Duration) -> Self {
+        let start = Duration::from_micros(self.end());
+        self.push_key(start, key, true)
+            .push_key(start + duration, key, false)
+    }
+
+    /// Adds `count` taps of `key` after the last action, starting
+    /// one every `interval`.
+    ///
+    /// Each tap holds the key down for half of the interval.
+    pub fn spam_key(mut self, key: Keycode, interval: Duration, count: usize) -> Self {
+        let mut start = Duration::from_micros(self.end());
+        for _ in 0..count {
+            self = self.push_key(start, key, true)
+                .push_key(start + interval / 2, key, false);
+            start += interval;
+        }
+
+        self
+    }
+
+    /// Adds an action at the given offset.
+    fn push(mut self, offset: Duration, action: UserAction) -> Self {
+        let offset = offset.as_micros() as u64;
+        self.actions.push(MacroAction { offset, action });
+        self
+    }
+
+    /// Returns the offset of the last action added.
+    fn end(&self) -> u64 {
+        self.actions.iter().map(|a| a.offset).max().unwrap_or(0)
+    }
+
+    /// Creates the macro.
+    ///
+    /// The macro connects to the input system, using the configured
+    /// settings, the first time it needs to, so macros can be built,
+    /// edited and saved without one.
+    pub fn build(self) -> Macro {
+        let metadata = MacroMetadata {
+            end: self.end(),
+            mouse_mode: self.mouse_mode,
+            ..Default::default()
+        };
+
+        // Added actions may be out of order; keep them in time order.
+        let mut actions = self.actions;
+        actions.sort_by_key(|a| a.offset);
+
+        let mut new = Macro::from_parts(actions, metadata);
+        new.settings = self.settings;
+        new.poll_interval = self.poll_interval;
+        new.remap = self.remap;
+        new.display_scale = self.display_scale;
+
+        new
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[should_panic]
+    fn reject_zero_interval() {
+        MacroBuilder::new().poll_interval(Duration::ZERO);
+    }
+
+    #[test]
+    fn push_actions() {
+        let builder = MacroBuilder::new()
+            .push_mouse_move(Duration::from_millis(10), 5, 5)
+            .push_key(Duration::from_millis(30), Keycode::A, true)
+            .push_mouse_button(Duration::from_millis(20), 1, true);
+
+        assert_eq!(builder.actions.len(), 3);
+        assert_eq!(builder.end(), 30_000);
+    }
+
+    #[test]
+    fn build_without_connecting() {
+        let new = MacroBuilder::new()
+            .push_key(Duration::from_millis(10), Keycode::A, true)
+            .push_key(Duration::from_millis(20), Keycode::A, false)
+            .build();
+
+        assert!(new.enigo.is_none());
+        assert_eq!(new.actions().len(), 2);
+        assert_eq!(new.duration(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn hold_and_spam_keys() {
+        let builder = MacroBuilder::new()
+            .push_key(Duration::from_millis(10), Keycode::LShift, true)
+            .hold_key(Keycode::W, Duration::from_millis(500))
+            .spam_key(Keycode::Space, Duration::from_millis(100), 3);
+        let keys = builder.actions.iter()
+            .map(|a| match &a.action {
+                UserAction::Key(key) => (a.offset, key.key.as_str(), key.pressed),
+                _ => unreachable!()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(keys, vec![
+            (10_000, "LShift", true),
+            (10_000, "W", true), (510_000, "W", false),
+            (510_000, "Space", true), (560_000, "Space", false),
+            (610_000, "Space", true), (660_000, "Space", false),
+            (710_000, "Space", true), (760_000, "Space", false)
+        ]);
+    }
+
+    #[test]
+    fn type_text_with_shift() {
+        let builder = MacroBuilder::new().type_text("a!\u{e9}");
+        let keys = builder.actions.iter()
+            .map(|a| match &a.action {
+                UserAction::Key(key) => (key.key.as_str(), key.pressed),
+                _ => unreachable!()
+            })
+            .collect::<Vec<_>>();
+
+        // The unmapped character is skipped.
+        assert_eq!(keys, vec![
+            ("A", true), ("A", false),
+            ("LShift", true), ("Key1", true), ("Key1", false), ("LShift", false)
+        ]);
+    }
+}
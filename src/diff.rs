@@ -0,0 +1,122 @@
+use crate::{ActionView, Macro, RemapTable};
+use crate::utils;
+
+/// A difference between the actions of two macros.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionDiff {
+    /// The action is only in the second macro.
+    Added(ActionView),
+    /// The action is only in the first macro.
+    Removed(ActionView),
+    /// The action happens at the same offset in both macros, but does
+    /// something different.
+    Changed { before: ActionView, after: ActionView }
+}
+
+impl Macro {
+    /// Lists the differences between the actions of this macro and `other`.
+    ///
+    /// Actions are matched up by offset, and actions which share an offset
+    /// by the order they were recorded in. An action which was moved to a
+    /// different offset is reported as removed from its old offset and
+    /// added at its new one.
+    pub fn diff(&self, other: &Macro) -> Vec<ActionDiff> {
+        let before = self.actions();
+        let after = other.actions();
+        let mut diffs = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        // Compare the actions at each offset, earliest first.
+        while let Some(offset) = before.get(i).into_iter().chain(after.get(j)).map(|a| a.offset).min() {
+            let old = before[i..].iter().take_while(|a| a.offset == offset).collect::<Vec<_>>();
+            let new = after[j..].iter().take_while(|a| a.offset == offset).collect::<Vec<_>>();
+
+            for index in 0..old.len().max(new.len()) {
+                match (old.get(index), new.get(index)) {
+                    (Some(a), Some(b)) if a != b => diffs.push(ActionDiff::Changed {
+                        before: (*a).clone(),
+                        after: (*b).clone()
+                    }),
+                    (Some(a), None) => diffs.push(ActionDiff::Removed((*a).clone())),
+                    (None, Some(b)) => diffs.push(ActionDiff::Added((*b).clone())),
+                    _ => {}
+                }
+            }
+
+            i += old.len();
+            j += new.len();
+        }
+
+        diffs
+    }
+}
+
+/// Macros are equal if they have the same actions and metadata.
+///
+/// How they play back, such as their settings and key remapping
+/// table, isn't compared.
+impl PartialEq for Macro {
+    fn eq(&self, other: &Self) -> bool {
+        // Copy the metadata first, in case both macros share it.
+        let metadata = utils::lock(&self.metadata).clone();
+        metadata == *utils::lock(&other.metadata) && views(self) == views(other)
+    }
+}
+
+/// Returns views of the macro's actions which don't depend on its remap table.
+fn views(new: &Macro) -> Vec<ActionView> {
+    let remap = RemapTable::new();
+    utils::lock(&new.actions).iter()
+        .map(|action| ActionView::new(action, &remap))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use crate::ActionKind;
+    use crate::macros::{KeyAction, MacroAction, MacroMetadata, MouseMoveAction, UserAction};
+
+    fn key(offset: u64, key: &str, pressed: bool) -> MacroAction {
+        MacroAction { offset, action: UserAction::Key(KeyAction { key: key.to_string(), pressed }) }
+    }
+
+    #[test]
+    fn diff_actions() {
+        let metadata = MacroMetadata { end: 30_000, cursor_pos: (0, 0), ..Default::default() };
+        let towa = Macro::from_parts(vec![
+            key(0, "A", true),
+            key(10_000, "A", false),
+            key(20_000, "B", true)
+        ], metadata.clone());
+        let other = Macro::from_parts(vec![
+            key(0, "A", true),
+            key(10_000, "C", false),
+            MacroAction { offset: 25_000, action: UserAction::MouseMove(MouseMoveAction { x: 1, y: 1 }) }
+        ], metadata);
+
+        let view = |offset, kind| ActionView { offset: Duration::from_micros(offset), kind };
+        assert_eq!(towa.diff(&other), vec![
+            ActionDiff::Changed {
+                before: view(10_000, ActionKind::Key { key: "A".to_string(), pressed: false, mapped: true }),
+                after: view(10_000, ActionKind::Key { key: "C".to_string(), pressed: false, mapped: true })
+            },
+            ActionDiff::Removed(view(20_000, ActionKind::Key { key: "B".to_string(), pressed: true, mapped: true })),
+            ActionDiff::Added(view(25_000, ActionKind::MouseMove { x: 1, y: 1 }))
+        ]);
+        assert!(towa.diff(&towa).is_empty());
+    }
+
+    #[test]
+    fn compare_macros() {
+        let metadata = MacroMetadata { end: 10_000, cursor_pos: (0, 0), ..Default::default() };
+        let towa = Macro::from_parts(vec![key(0, "A", true)], metadata.clone());
+
+        assert_eq!(towa, Macro::from_parts(vec![key(0, "A", true)], metadata.clone()));
+        assert_ne!(towa, Macro::from_parts(vec![key(0, "A", false)], metadata));
+
+        // A macro equals its clones, which share its state.
+        assert_eq!(towa, towa.clone());
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use crate::macros::{MacroAction, MacroMetadata};
+use crate::{ActionView, Macro, RemapTable};
+use crate::utils;
+
+/// A lock on the actions of a macro which allows them to be edited.
+///
+/// Indices refer to the actions in time order, as they were when the
+/// lock was taken. Changing an offset doesn't move the action until
+/// the lock is dropped, when the actions are sorted by offset again
+/// and the macro is extended to end no earlier than its last action.
+#[derive(Debug)]
+pub struct ActionsMut<'a> {
+    actions: MutexGuard<'a, Vec<MacroAction>>,
+    metadata: &'a Mutex<MacroMetadata>,
+    remap: &'a RemapTable
+}
+
+impl ActionsMut<'_> {
+    /// Returns the number of actions.
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Checks if there are no actions.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Returns a view of the action at `index`, if there is one.
+    pub fn get(&self, index: usize) -> Option<ActionView> {
+        self.actions.get(index).map(|action| ActionView::new(action, self.remap))
+    }
+
+    /// Removes the action at `index`, returning a view of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> ActionView {
+        ActionView::new(&self.actions.remove(index), self.remap)
+    }
+
+    /// Keeps only the actions for which `keep` returns `true`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&ActionView) -> bool) {
+        let remap = self.remap;
+        self.actions.retain(|action| keep(&ActionView::new(action, remap)));
+    }
+
+    /// Changes when the action at `index` happens.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_offset(&mut self, index: usize, offset: Duration) {
+        self.actions[index].offset = offset.as_micros() as u64;
+    }
+
+    /// Swaps the actions at `a` and `b`, leaving their offsets in place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        let (first, second) = (self.actions[a].offset, self.actions[b].offset);
+        self.actions.swap(a, b);
+        self.actions[a].offset = first;
+        self.actions[b].offset = second;
+    }
+}
+
+impl Drop for ActionsMut<'_> {
+    fn drop(&mut self) {
+        // Keep actions in time order; a stable sort keeps actions
+        // which share an offset in the order they were left in.
+        self.actions.sort_by_key(|a| a.offset);
+
+        // Make sure the macro doesn't end before its last action.
+        if let Some(last) = self.actions.last() {
+            let mut metadata = utils::lock(self.metadata);
+            metadata.end = metadata.end.max(last.offset);
+        }
+    }
+}
+
+impl Macro {
+    /// Locks the actions of this macro for editing.
+    ///
+    /// The macro can't be recorded into or played back until the
+    /// returned lock is dropped.
+    pub fn actions_mut(&self) -> ActionsMut<'_> {
+        ActionsMut {
+            actions: utils::lock(&self.actions),
+            metadata: &self.metadata,
+            remap: &self.remap
+        }
+    }
+
+    /// Delays every action at or after `from` by `by`, and the end of
+    /// the macro along with them.
+    ///
+    /// This inserts a pause into the macro at `from`.
+    pub fn shift_after(&mut self, from: Duration, by: Duration) {
+        let from = from.as_micros() as u64;
+        let by = by.as_micros() as u64;
+        let mut actions = utils::lock(&self.actions);
+        let mut metadata = utils::lock(&self.metadata);
+
+        for action in actions.iter_mut().filter(|a| a.offset >= from) {
+            action.offset += by;
+        }
+        if metadata.end >= from {
+            metadata.end += by;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ActionKind;
+    use crate::macros::{KeyAction, MouseButtonAction, UserAction};
+
+    fn towa() -> Macro {
+        let actions = vec![
+            MacroAction { offset: 0, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            MacroAction { offset: 10_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: false }) },
+            MacroAction { offset: 20_000, action: UserAction::MouseButton(MouseButtonAction { button: 1, pressed: true }) },
+        ];
+        Macro::from_parts(actions, MacroMetadata { end: 30_000, cursor_pos: (0, 0), ..Default::default() })
+    }
+
+    #[test]
+    fn edit_actions() {
+        let towa = towa();
+
+        let mut actions = towa.actions_mut();
+        actions.set_offset(0, Duration::from_millis(40));
+        actions.retain(|view| !matches!(view.kind, ActionKind::MouseButton { .. }));
+        drop(actions);
+
+        // The moved action is sorted to the end, and the macro extended to fit it.
+        let offsets = towa.actions().iter().map(|a| a.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![Duration::from_millis(10), Duration::from_millis(40)]);
+        assert_eq!(towa.duration(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn shift_actions_after() {
+        let mut towa = towa();
+        towa.shift_after(Duration::from_millis(10), Duration::from_millis(5));
+
+        let offsets = towa.actions().iter().map(|a| a.offset.as_micros()).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 15_000, 25_000]);
+        assert_eq!(towa.duration(), Duration::from_millis(35));
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+use enigo::{InputError, NewConError};
+
+/// An error which occurs while loading a macro from the file system.
+#[cfg(any(feature = "save", feature = "binary"))]
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file does not contain a valid macro.
+    #[cfg(feature = "save")]
+    Json(serde_json::Error),
+    /// The file does not contain a valid binary macro.
+    #[cfg(feature = "binary")]
+    Binary(bincode::Error)
+}
+
+#[cfg(any(feature = "save", feature = "binary"))]
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "failed to read macro: {}", e),
+            #[cfg(feature = "save")]
+            LoadError::Json(e) => write!(f, "failed to parse macro: {}", e),
+            #[cfg(feature = "binary")]
+            LoadError::Binary(e) => write!(f, "failed to decode macro: {}", e)
+        }
+    }
+}
+
+#[cfg(any(feature = "save", feature = "binary"))]
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            #[cfg(feature = "save")]
+            LoadError::Json(e) => Some(e),
+            #[cfg(feature = "binary")]
+            LoadError::Binary(e) => Some(e)
+        }
+    }
+}
+
+#[cfg(any(feature = "save", feature = "binary"))]
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+#[cfg(feature = "save")]
+impl From<serde_json::Error> for LoadError {
+    fn from(e: serde_json::Error) -> Self {
+        LoadError::Json(e)
+    }
+}
+
+#[cfg(feature = "binary")]
+impl From<bincode::Error> for LoadError {
+    fn from(e: bincode::Error) -> Self {
+        LoadError::Binary(e)
+    }
+}
+
+/// An error which occurs while parsing a macro script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    line: usize,
+    message: String
+}
+
+impl ScriptError {
+    pub(crate) fn new<S: Into<String>>(line: usize, message: S) -> Self {
+        ScriptError { line, message: message.into() }
+    }
+
+    /// Returns the line of the script which the error is on, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// An error which stops a macro from being played back.
+///
+/// Any keys and mouse buttons which the playback was holding down
+/// are released before the error is returned.
+#[derive(Debug)]
+pub enum PlaybackError {
+    /// A connection to the input system could not be made.
+    Connection(NewConError),
+    /// The input system failed to perform an action.
+    Input(InputError),
+    /// The focused window isn't the one the macro was recorded in.
+    WrongWindow {
+        /// The title of the window the macro was recorded in.
+        expected: String,
+        /// The title of the window which has focus.
+        found: String
+    }
+}
+
+impl Display for PlaybackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlaybackError::Connection(e) => write!(f, "failed to connect to the input system: {}", e),
+            PlaybackError::Input(e) => write!(f, "failed to play back action: {}", e),
+            PlaybackError::WrongWindow { expected, found } =>
+                write!(f, "macro was recorded in `{}`, but `{}` has focus", expected, found)
+        }
+    }
+}
+
+impl std::error::Error for PlaybackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlaybackError::Connection(e) => Some(e),
+            PlaybackError::Input(e) => Some(e),
+            PlaybackError::WrongWindow { .. } => None
+        }
+    }
+}
+
+impl From<NewConError> for PlaybackError {
+    fn from(e: NewConError) -> Self {
+        PlaybackError::Connection(e)
+    }
+}
+
+impl From<InputError> for PlaybackError {
+    fn from(e: InputError) -> Self {
+        PlaybackError::Input(e)
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::macros::{self, MacroAction, MacroMetadata, UserAction, FORMAT_VERSION};
+use crate::Macro;
+use crate::utils;
+
+/// A compact form of a `Macro` for serialization.
+///
+/// Every distinct key name is stored once in a table, and key
+/// actions refer to their key by its index into that table.
+/// Macros with many key presses serialize much smaller this way.
+///
+/// Like a `Macro`, it stores the version of the format it was saved
+/// with, and older versions are upgraded when deserialized.
+///
+/// Use `Macro::intern` to create one, and `Macro::from` to turn
+/// a deserialized one back into a playable macro.
+#[derive(Debug, Clone, Serialize)]
+pub struct InternedMacro {
+    version: u32,
+    keys: Vec<String>,
+    actions: Vec<MacroAction<usize>>,
+    metadata: MacroMetadata
+}
+
+impl InternedMacro {
+    /// Builds the key table for the given actions.
+    pub(crate) fn new(actions: &[MacroAction], metadata: &MacroMetadata) -> Self {
+        let mut keys = Vec::new();
+        let mut indices = HashMap::new();
+
+        let actions = actions.iter()
+            .map(|a| MacroAction {
+                offset: a.offset,
+                action: a.action.map_key(|key| {
+                    *indices.entry(key.clone()).or_insert_with(|| {
+                        keys.push(key.clone());
+                        keys.len() - 1
+                    })
+                })
+            })
+            .collect();
+
+        InternedMacro {
+            version: FORMAT_VERSION,
+            keys,
+            actions,
+            metadata: metadata.clone()
+        }
+    }
+
+    /// Resolves every key index back into its key name.
+    pub(crate) fn into_parts(self) -> (Vec<MacroAction>, MacroMetadata) {
+        let actions = self.actions.iter()
+            .map(|a| MacroAction {
+                offset: a.offset,
+                action: a.action.map_key(|index| self.keys[*index].clone())
+            })
+            .collect();
+
+        (actions, self.metadata)
+    }
+}
+
+impl<'de> Deserialize<'de> for InternedMacro {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        #[derive(Deserialize)]
+        #[serde(rename = "InternedMacro")]
+        struct Parts {
+            // Interned macros without a version stored offsets in milliseconds.
+            #[serde(default = "first_version")]
+            version: u32,
+            keys: Vec<String>,
+            actions: Vec<MacroAction<usize>>,
+            metadata: MacroMetadata
+        }
+
+        fn first_version() -> u32 {
+            1
+        }
+
+        let mut parts = Parts::deserialize(deserializer)?;
+        macros::check_version::<D::Error>(parts.version)?;
+
+        // Make sure every key refers to an entry in the table.
+        for action in parts.actions.iter() {
+            if let UserAction::Key(key) = &action.action
+                && key.key >= parts.keys.len() {
+                return Err(serde::de::Error::custom(format!(
+                    "key index {} out of range for {} keys", key.key, parts.keys.len())));
+            }
+        }
+
+        if parts.version < 2 {
+            for action in parts.actions.iter_mut() {
+                action.offset = macros::millis_to_micros(action.offset)?;
+            }
+            parts.metadata.end = macros::millis_to_micros(parts.metadata.end)?;
+        }
+
+        Ok(InternedMacro {
+            version: FORMAT_VERSION,
+            keys: parts.keys,
+            actions: parts.actions,
+            metadata: parts.metadata
+        })
+    }
+}
+
+impl Macro {
+    /// Creates a compact copy of this macro for serialization.
+    pub fn intern(&self) -> InternedMacro {
+        let actions = utils::lock(&self.actions);
+        let metadata = utils::lock(&self.metadata);
+        InternedMacro::new(&actions, &metadata)
+    }
+}
+
+impl From<InternedMacro> for Macro {
+    fn from(interned: InternedMacro) -> Self {
+        let (mut actions, metadata) = interned.into_parts();
+        actions.sort_by_key(|a| a.offset);
+        Macro::from_parts(actions, metadata)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::macros::KeyAction;
+
+    #[test]
+    fn interned_is_smaller() {
+        let actions = (0..500)
+            .map(|i| MacroAction {
+                offset: i * 1000,
+                action: UserAction::Key(KeyAction {
+                    key: if i % 3 == 0 { "LShift" } else { "Backspace" }.to_string(),
+                    pressed: i % 2 == 0
+                })
+            })
+            .collect::<Vec<_>>();
+        let metadata = MacroMetadata { end: 500_000, cursor_pos: (0, 0), ..Default::default() };
+
+        let plain = serde_json::json!({ "actions": actions, "metadata": metadata }).to_string();
+        let interned = serde_json::to_string(&InternedMacro::new(&actions, &metadata))
+            .expect("failed to serialize interned macro");
+        assert!(interned.len() < plain.len());
+
+        let restored: InternedMacro = serde_json::from_str(&interned)
+            .expect("failed to deserialize interned macro");
+        let (restored, _) = restored.into_parts();
+
+        let keys = |actions: &[MacroAction]| actions.iter()
+            .map(|a| match &a.action {
+                UserAction::Key(key) => key.key.clone(),
+                _ => unreachable!()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys(&restored), keys(&actions));
+    }
+
+    #[test]
+    fn reject_out_of_range_key() {
+        let content = r#"{
+            "keys": ["A"],
+            "actions": [{ "action": { "Key": { "key": 1, "pressed": true } }, "offset": 0 }],
+            "metadata": { "end": 10, "cursor_pos": [0, 0] }
+        }"#;
+
+        assert!(serde_json::from_str::<InternedMacro>(content).is_err());
+    }
+
+    #[test]
+    fn upgrade_unversioned_interned() {
+        let content = r#"{
+            "keys": ["A"],
+            "actions": [{ "action": { "Key": { "key": 0, "pressed": true } }, "offset": 5 }],
+            "metadata": { "end": 10, "cursor_pos": [0, 0] }
+        }"#;
+
+        let interned: InternedMacro = serde_json::from_str(content)
+            .expect("failed to deserialize interned macro");
+        let serialized = serde_json::to_value(&interned).expect("failed to serialize interned macro");
+        assert_eq!(serialized["version"], FORMAT_VERSION);
+
+        let (actions, metadata) = interned.into_parts();
+        assert_eq!(actions[0].offset, 5_000);
+        assert_eq!(metadata.end, 10_000);
+    }
+
+    #[test]
+    fn reject_overflowing_interned_offsets() {
+        let content = format!(r#"{{
+            "keys": [],
+            "actions": [],
+            "metadata": {{ "end": {}, "cursor_pos": [0, 0] }}
+        }}"#, u64::MAX);
+
+        assert!(serde_json::from_str::<InternedMacro>(&content).is_err());
+    }
+
+    #[test]
+    fn reject_newer_interned_version() {
+        let content = format!(r#"{{
+            "version": {},
+            "keys": [],
+            "actions": [],
+            "metadata": {{ "end": 10, "cursor_pos": [0, 0] }}
+        }}"#, FORMAT_VERSION + 1);
+
+        assert!(serde_json::from_str::<InternedMacro>(&content).is_err());
+    }
+}
@@ -2,8 +2,30 @@
 //!
 //! A lightweight macro library for recording & playing back keyboard and mouse events.
 mod macros;
+mod builder;
+mod remap;
+mod intern;
+mod view;
+mod edit;
+mod script;
+mod summary;
+mod validate;
+mod diff;
+mod error;
 pub(crate) mod utils;
 
-pub use macros::Macro;
+pub use macros::{Macro, PlaybackHandle, PlaybackCursor, PlaybackOptions, PlaybackOutcome, PlaybackEvent, MouseMode, RecordFilter, JitterConfig, WindowCheck};
+pub use builder::MacroBuilder;
+pub use remap::RemapTable;
+pub use intern::InternedMacro;
+pub use view::{key_label, ActionKind, ActionView};
+pub use edit::ActionsMut;
+pub use summary::MacroSummary;
+pub use validate::ValidationIssue;
+pub use diff::ActionDiff;
+#[cfg(any(feature = "save", feature = "binary"))]
+pub use error::LoadError;
+pub use error::{PlaybackError, ScriptError};
 
-pub use device_query::Keycode;
\ No newline at end of file
+pub use device_query::Keycode;
+pub use enigo::{Key, Settings};
\ No newline at end of file
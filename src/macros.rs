@@ -1,52 +1,792 @@
+use std::borrow::Cow;
 use std::fmt::Formatter;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+use std::sync::mpsc::Sender;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
-use device_query::{DeviceEvents, DeviceEventsHandler, DeviceQuery, DeviceState, MouseButton};
-use enigo::{Button, Coordinate, Direction, Enigo, Keyboard, Mouse, Settings};
+use device_query::{DeviceEvents, DeviceEventsHandler, DeviceQuery, DeviceState, Keycode, MouseButton};
+use enigo::{Axis, Button, Coordinate, Direction, Enigo, InputError, Key, Keyboard, Mouse, NewConError, Settings};
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
-use serde::de::{MapAccess, Visitor};
+use serde::de::{MapAccess, SeqAccess, Visitor};
 use serde::ser::SerializeStruct;
 use crate::utils;
+use crate::{ActionView, MacroBuilder, PlaybackError, RemapTable};
+#[cfg(any(feature = "save", feature = "binary"))]
+use crate::LoadError;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct MouseMoveAction {
-    x: i32,
-    y: i32
+    pub(crate) x: i32,
+    pub(crate) y: i32
+}
+
+impl MouseMoveAction {
+    /// Returns the position to move to, scaled and shifted by the given options.
+    fn position(&self, options: &PlaybackOptions) -> (i32, i32) {
+        options.place((self.x, self.y))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct MouseButtonAction {
-    button: MouseButton,
-    pressed: bool
+    pub(crate) button: MouseButton,
+    pub(crate) pressed: bool
+}
+
+/// The direction a mouse scroll moves in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ScrollAxis {
+    #[default]
+    Vertical,
+    Horizontal
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct MouseScrollAction {
+    /// The number of steps to scroll; positive values scroll
+    /// down or to the right.
+    pub(crate) delta: i32,
+    #[serde(default)]
+    pub(crate) axis: ScrollAxis
 }
 
+/// A key action stores the name of the key as `K`.
+///
+/// Recorded macros always use the key's name, but the serialized
+/// form may replace it with an index into a table of names.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct KeyAction {
-    key: String,
-    pressed: bool
+pub(crate) struct KeyAction<K = String> {
+    pub(crate) key: K,
+    pub(crate) pressed: bool
 }
 
 /// A user action represents the types of actions that can be
 /// recorded and the data associated with them.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) enum UserAction {
+pub(crate) enum UserAction<K = String> {
     MouseMove(MouseMoveAction),
     MouseButton(MouseButtonAction),
-    Key(KeyAction)
+    MouseScroll(MouseScrollAction),
+    Key(KeyAction<K>)
+}
+
+impl<K> UserAction<K> {
+    /// Checks if this action is a mouse button release.
+    fn is_release(&self) -> bool {
+        matches!(self, UserAction::MouseButton(mouse) if !mouse.pressed)
+    }
+
+    /// Converts the key stored in this action (if any) using `f`.
+    pub(crate) fn map_key<T>(&self, f: impl FnOnce(&K) -> T) -> UserAction<T> {
+        match self {
+            UserAction::MouseMove(mouse) => UserAction::MouseMove(mouse.clone()),
+            UserAction::MouseButton(mouse) => UserAction::MouseButton(mouse.clone()),
+            UserAction::MouseScroll(scroll) => UserAction::MouseScroll(scroll.clone()),
+            UserAction::Key(key) => UserAction::Key(KeyAction {
+                key: f(&key.key),
+                pressed: key.pressed
+            })
+        }
+    }
+}
+
+impl UserAction {
+    /// Plays this action back using the given `Enigo` instance.
+    ///
+    /// Mouse moves are interpreted according to `mode`,
+    /// and keys are looked up in `remap`.
+    /// The result of the action is recorded in `outcome`.
+    /// Keys and buttons which are pressed are tracked in `held`.
+    ///
+    /// Returns an error if the input system fails to perform the action.
+    fn play(&self, enigo: &mut Enigo, mode: MouseMode, remap: &RemapTable, options: &PlaybackOptions, held: &mut Held, outcome: &mut PlaybackOutcome) -> Result<(), InputError> {
+        match self {
+            UserAction::MouseMove(mouse) => match mode {
+                MouseMode::Absolute => {
+                    let (x, y) = mouse.position(options);
+                    enigo.move_mouse(x, y, Coordinate::Abs)?;
+                }
+                MouseMode::Relative => {
+                    enigo.move_mouse(mouse.x, mouse.y, Coordinate::Rel)?;
+                }
+            }
+            UserAction::MouseButton(mouse) => {
+                let direction = if mouse.pressed {
+                    Direction::Press
+                } else {
+                    Direction::Release
+                };
+                let Some(button) = utils::remap_button(mouse.button) else {
+                    log::warn!("Unknown mouse button: {}", mouse.button);
+                    outcome.unknown_buttons += 1;
+                    return Ok(());
+                };
+
+                enigo.button(button, direction)?;
+                held.button(button, mouse.pressed);
+            }
+            UserAction::MouseScroll(scroll) => {
+                let axis = match scroll.axis {
+                    ScrollAxis::Vertical => Axis::Vertical,
+                    ScrollAxis::Horizontal => Axis::Horizontal
+                };
+
+                enigo.scroll(scroll.delta, axis)?;
+            }
+            UserAction::Key(action) => {
+                let direction = if action.pressed {
+                    Direction::Press
+                } else {
+                    Direction::Release
+                };
+
+                let Some(key) = remap.get(&action.key) else {
+                    log::warn!("No key to play back `{}` as", action.key);
+                    outcome.skipped_keys += 1;
+                    return Ok(());
+                };
+                enigo.key(key, direction)?;
+                held.key(key, action.pressed);
+            }
+        }
+
+        outcome.dispatched += 1;
+        Ok(())
+    }
+}
+
+/// The keys and mouse buttons held down during a playback.
+#[derive(Debug, Default)]
+struct Held {
+    keys: Vec<Key>,
+    buttons: Vec<Button>
+}
+
+impl Held {
+    /// Records a key being pressed or released.
+    fn key(&mut self, key: Key, pressed: bool) {
+        self.keys.retain(|k| *k != key);
+        if pressed {
+            self.keys.push(key);
+        }
+    }
+
+    /// Records a mouse button being pressed or released.
+    fn button(&mut self, button: Button, pressed: bool) {
+        self.buttons.retain(|b| *b != button);
+        if pressed {
+            self.buttons.push(button);
+        }
+    }
+
+    /// Releases everything which is still held down, returning
+    /// how many keys and buttons were released.
+    ///
+    /// Errors are ignored, since this is a best effort to leave
+    /// the system in a usable state.
+    fn release_all(&mut self, enigo: &mut Enigo) -> usize {
+        let count = self.keys.len() + self.buttons.len();
+        for key in self.keys.drain(..).rev() {
+            let _ = enigo.key(key, Direction::Release);
+        }
+        for button in self.buttons.drain(..).rev() {
+            let _ = enigo.button(button, Direction::Release);
+        }
+
+        count
+    }
+}
+
+/// A handle to a macro playing back on another thread.
+///
+/// Dropping the handle lets the playback run to completion.
+#[derive(Debug)]
+pub struct PlaybackHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<PlaybackOutcome, PlaybackError>>
+}
+
+impl PlaybackHandle {
+    /// Asks the playback to stop.
+    ///
+    /// The playback stops at its next check, and releases any keys
+    /// and mouse buttons it was holding down.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks if the playback has finished.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the playback to finish.
+    ///
+    /// Returns an error if the playback thread panicked, and
+    /// the result of the playback otherwise.
+    pub fn join(self) -> std::thread::Result<Result<PlaybackOutcome, PlaybackError>> {
+        self.thread.join()
+    }
+}
+
+/// Plays a macro back one action at a time, ignoring its timing.
+///
+/// Each call to `next` plays the next action and returns a view of
+/// it. Anything still held down is released when the cursor is dropped.
+/// Use `Macro::step_playback` to create one.
+#[derive(Debug)]
+pub struct PlaybackCursor<'a> {
+    enigo: &'a mut Enigo,
+    remap: &'a RemapTable,
+    actions: Vec<MacroAction>,
+    mouse_mode: MouseMode,
+    options: PlaybackOptions,
+    next: usize,
+    held: Held,
+    outcome: PlaybackOutcome
+}
+
+impl PlaybackCursor<'_> {
+    /// Returns a summary of the actions played so far.
+    pub fn outcome(&self) -> PlaybackOutcome {
+        self.outcome
+    }
+
+    /// Returns the number of actions which haven't been played yet.
+    pub fn remaining(&self) -> usize {
+        self.actions.len() - self.next
+    }
+}
+
+impl Iterator for PlaybackCursor<'_> {
+    type Item = Result<ActionView, PlaybackError>;
+
+    /// Plays the next action, returning what it did.
+    fn next(&mut self) -> Option<Self::Item> {
+        let action = self.actions.get(self.next)?;
+        self.next += 1;
+
+        let played = action.action.play(self.enigo, self.mouse_mode, self.remap, &self.options, &mut self.held, &mut self.outcome);
+        Some(played.map(|_| ActionView::new(action, self.remap)).map_err(PlaybackError::from))
+    }
+}
+
+impl Drop for PlaybackCursor<'_> {
+    fn drop(&mut self) {
+        // Don't leave anything pressed, even if the macro never released it.
+        self.outcome.released += self.held.release_all(self.enigo);
+    }
+}
+
+/// A summary of a macro playback.
+///
+/// Playback doesn't abort because of a single action which has no
+/// key or button to play back as; those actions are skipped and
+/// counted here instead.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlaybackOutcome {
+    /// The number of actions which were sent to the system.
+    pub dispatched: usize,
+    /// The number of key actions skipped because the key has no mapping.
+    pub skipped_keys: usize,
+    /// The number of mouse button actions skipped because the button is unknown.
+    pub unknown_buttons: usize,
+    /// The number of keys and buttons still held down when the playback
+    /// ended, which were released automatically.
+    pub released: usize,
+    /// How far the cursor ended up from where the macro's relative
+    /// moves should have left it, before any correction.
+    ///
+    /// This is only measured for macros using `MouseMode::Relative`.
+    pub drift: Option<(i32, i32)>
+}
+
+/// The kinds of user actions to record.
+///
+/// Filters can be combined with `|`, such as
+/// `RecordFilter::KEYS | RecordFilter::MOUSE_BUTTONS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordFilter(u8);
+
+impl RecordFilter {
+    /// Key presses and releases.
+    pub const KEYS: RecordFilter = RecordFilter(1);
+    /// Mouse button presses and releases.
+    pub const MOUSE_BUTTONS: RecordFilter = RecordFilter(1 << 1);
+    /// Mouse movements.
+    pub const MOUSE_MOVE: RecordFilter = RecordFilter(1 << 2);
+    /// Every kind of action.
+    pub const ALL: RecordFilter = RecordFilter(0b111);
+
+    /// Checks if every kind of action in `other` is in this filter.
+    pub fn contains(self, other: RecordFilter) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for RecordFilter {
+    fn default() -> Self {
+        RecordFilter::ALL
+    }
+}
+
+impl std::ops::BitOr for RecordFilter {
+    type Output = RecordFilter;
+
+    fn bitor(self, other: Self) -> Self {
+        RecordFilter(self.0 | other.0)
+    }
+}
+
+/// An event sent while a macro is played back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackEvent {
+    /// The playback has started.
+    Started,
+    /// An action was played.
+    Action {
+        /// The action which was played.
+        view: ActionView,
+        /// How far into the playback the action was played.
+        offset: Duration
+    },
+    /// The playback has finished.
+    Finished(PlaybackOutcome)
+}
+
+/// Options which change how a macro is played back.
+///
+/// None of these options modify the stored macro.
+#[derive(Debug, Clone)]
+pub struct PlaybackOptions {
+    /// The multiplier applied to the playback speed.
+    speed: f64,
+    /// The amount to shift every mouse movement by.
+    shift: (i32, i32),
+    /// The factors to scale absolute mouse positions by.
+    scale: (f64, f64),
+    /// The pause inserted after every mouse button release.
+    post_click_delay: Duration,
+    /// The maximum amount to randomly shift each action by.
+    humanize: Duration,
+    /// The seed used to generate the random shifts.
+    seed: u64,
+    /// The maximum distance in pixels to randomly stray from each mouse move.
+    wander: u32,
+    /// The longest time to sleep between checks for due actions.
+    poll_interval: Duration,
+    /// The number of smaller moves each mouse move is split into.
+    smooth_steps: u32,
+    /// Whether to move the cursor to where relative moves should have left it.
+    correct_drift: bool,
+    /// What to do if the focused window isn't the one recorded in.
+    window_check: WindowCheck,
+    /// A flag which stops the playback when set.
+    stop: Option<Arc<AtomicBool>>
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        PlaybackOptions {
+            speed: 1.0,
+            shift: (0, 0),
+            scale: (1.0, 1.0),
+            post_click_delay: Duration::ZERO,
+            humanize: Duration::ZERO,
+            seed: 0,
+            wander: 0,
+            poll_interval: MAX_PLAYBACK_SLEEP,
+            smooth_steps: 1,
+            correct_drift: false,
+            window_check: WindowCheck::Ignore,
+            stop: None
+        }
+    }
+}
+
+impl PlaybackOptions {
+    /// Creates the default playback options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the playback speed multiplier.
+    ///
+    /// At a speed of `2.0`, an action recorded 1 second into the
+    /// macro is played back after half a second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is not a positive, finite number.
+    pub fn speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0 && speed.is_finite(), "playback speed must be positive, got {}", speed);
+        self.speed = speed;
+        self
+    }
+
+    /// Sets a pause to insert after every mouse button release.
+    ///
+    /// Some applications need a moment after a click before they
+    /// register the next action. Every action after the click is
+    /// pushed back by this amount.
+    pub fn post_click_delay(mut self, delay: Duration) -> Self {
+        self.post_click_delay = delay;
+        self
+    }
+
+    /// Sets the longest time to sleep between checks for due actions.
+    ///
+    /// Playback sleeps until the next action is due, but wakes up at
+    /// least this often to check if it's been stopped and to report
+    /// progress. Longer intervals use less CPU during long pauses,
+    /// at the cost of reacting to being stopped more slowly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "polling interval must be non-zero");
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Randomly shifts the timing of each action by up to `amount`
+    /// in either direction.
+    ///
+    /// The same `seed` always produces the same timing, and actions
+    /// are never reordered by the shift.
+    pub fn humanize(mut self, amount: Duration, seed: u64) -> Self {
+        self.humanize = amount;
+        self.seed = seed;
+        self
+    }
+
+    /// Randomizes the timing and mouse movement of the playback.
+    ///
+    /// See `JitterConfig` for what is randomized.
+    pub fn jitter(mut self, config: JitterConfig) -> Self {
+        self.humanize = config.timing;
+        self.seed = config.seed;
+        self.wander = config.movement;
+        self
+    }
+
+    /// Splits every mouse move into `steps` smaller moves, spread
+    /// out over the time since the previous action.
+    ///
+    /// Smoothed moves glide the cursor along a straight line instead
+    /// of jumping it, and still end exactly where the recorded move
+    /// did. A value of `1` plays each move as recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `steps` is zero.
+    pub fn smooth(mut self, steps: u32) -> Self {
+        assert!(steps > 0, "smoothing needs at least one step");
+        self.smooth_steps = steps;
+        self
+    }
+
+    /// Moves the cursor to where the macro's relative moves should have
+    /// left it once the playback ends.
+    ///
+    /// Relative moves can add up to the wrong place if the cursor started
+    /// somewhere else, or hit the edge of the screen. This has no effect
+    /// on macros using `MouseMode::Absolute`.
+    pub fn correct_drift(mut self, correct: bool) -> Self {
+        self.correct_drift = correct;
+        self
+    }
+
+    /// Sets what to do if the focused window isn't the one the macro
+    /// was recorded in when the playback starts.
+    pub fn check_window(mut self, check: WindowCheck) -> Self {
+        self.window_check = check;
+        self
+    }
+
+    /// Checks the focused window against the one the macro was recorded in.
+    fn check_focused_window(&self, metadata: &MacroMetadata) -> Result<(), PlaybackError> {
+        if self.window_check == WindowCheck::Ignore {
+            return Ok(());
+        }
+        let (Some(expected), Some(found)) = (&metadata.window_title, utils::window_title()) else {
+            return Ok(());
+        };
+        if *expected == found {
+            return Ok(());
+        }
+
+        match self.window_check {
+            WindowCheck::Require => Err(PlaybackError::WrongWindow { expected: expected.clone(), found }),
+            _ => {
+                log::warn!("Macro was recorded in `{}`, but `{}` has focus", expected, found);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the actions to play back, with mouse moves split up if smoothing is enabled.
+    ///
+    /// Moves are only spread over the time since the previous action of
+    /// any kind, so the cursor is always in place for clicks.
+    fn smoothed<'a>(&self, actions: &'a [MacroAction], metadata: &MacroMetadata) -> Cow<'a, [MacroAction]> {
+        if self.smooth_steps <= 1 {
+            return Cow::Borrowed(actions);
+        }
+
+        let steps = self.smooth_steps as u64;
+        let mut smoothed = Vec::with_capacity(actions.len());
+        let mut previous = 0;
+        let mut position = metadata.cursor_pos;
+        // Carry the rounding error of relative steps across moves.
+        let mut sub_pixel = utils::SubPixel::default();
+
+        for action in actions {
+            let UserAction::MouseMove(mouse) = &action.action else {
+                previous = action.offset;
+                smoothed.push(action.clone());
+                continue;
+            };
+
+            let duration = action.offset.saturating_sub(previous);
+            let from = position;
+
+            for step in 1..=steps {
+                let offset = previous + duration * step / steps;
+                let (x, y) = match metadata.mouse_mode {
+                    MouseMode::Absolute => {
+                        // Interpolate between the previous position and the target.
+                        let fraction = step as f64 / steps as f64;
+                        let x = from.0 + ((mouse.x - from.0) as f64 * fraction).round() as i32;
+                        let y = from.1 + ((mouse.y - from.1) as f64 * fraction).round() as i32;
+                        if (x, y) == position {
+                            continue;
+                        }
+                        position = (x, y);
+                        (x, y)
+                    }
+                    MouseMode::Relative => {
+                        // Carry the rounding error so the steps add up to the move.
+                        let (dx, dy) = sub_pixel.step(mouse.x as f64 / steps as f64, mouse.y as f64 / steps as f64);
+                        if (dx, dy) == (0, 0) {
+                            continue;
+                        }
+                        (dx, dy)
+                    }
+                };
+
+                smoothed.push(MacroAction { offset, action: UserAction::MouseMove(MouseMoveAction { x, y }) });
+            }
+
+            previous = action.offset;
+        }
+
+        Cow::Owned(smoothed)
+    }
+
+    /// Returns the actions to play back, with a detour added to each
+    /// mouse move if movement jitter is enabled.
+    ///
+    /// Each move is split in two: a move partway there which strays by
+    /// up to `wander` pixels, halfway through the time since the previous
+    /// action, and a move which ends exactly where the recorded move did.
+    fn wandered<'a>(&self, actions: Cow<'a, [MacroAction]>, metadata: &MacroMetadata) -> Cow<'a, [MacroAction]> {
+        if self.wander == 0 {
+            return actions;
+        }
+
+        // Use a different stream of numbers than the timing jitter.
+        let mut rng = utils::Rng::new(!self.seed);
+        let wander = self.wander as i32;
+        let mut stray = || rng.below(wander as u64 * 2 + 1) as i32 - wander;
+
+        let mut wandered = Vec::with_capacity(actions.len() * 2);
+        let mut previous = 0;
+        let mut position = metadata.cursor_pos;
+
+        for action in actions.iter() {
+            let UserAction::MouseMove(mouse) = &action.action else {
+                previous = action.offset;
+                wandered.push(action.clone());
+                continue;
+            };
+
+            let (dx, dy) = (stray(), stray());
+            let (first, second) = match metadata.mouse_mode {
+                MouseMode::Absolute => {
+                    let halfway = ((position.0 + mouse.x) / 2 + dx, (position.1 + mouse.y) / 2 + dy);
+                    position = (mouse.x, mouse.y);
+                    (halfway, position)
+                }
+                MouseMode::Relative => {
+                    // The two moves still add up to the recorded one.
+                    let halfway = (mouse.x / 2 + dx, mouse.y / 2 + dy);
+                    (halfway, (mouse.x - halfway.0, mouse.y - halfway.1))
+                }
+            };
+
+            let halfway = previous + action.offset.saturating_sub(previous) / 2;
+            wandered.push(MacroAction { offset: halfway, action: UserAction::MouseMove(MouseMoveAction { x: first.0, y: first.1 }) });
+            wandered.push(MacroAction { offset: action.offset, action: UserAction::MouseMove(MouseMoveAction { x: second.0, y: second.1 }) });
+            previous = action.offset;
+        }
+
+        Cow::Owned(wandered)
+    }
+
+    /// Scales absolute mouse positions by the given factors.
+    ///
+    /// A macro recorded on a 2560×1440 display can be played back on
+    /// a 1920×1080 one with a scale of `0.75` in both directions.
+    /// Relative mouse moves aren't scaled; see `Macro::playback_scaled`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either factor is not a positive, finite number.
+    pub fn scale(mut self, x: f64, y: f64) -> Self {
+        assert!(x > 0.0 && x.is_finite() && y > 0.0 && y.is_finite(), "scale must be positive, got {}x{}", x, y);
+        self.scale = (x, y);
+        self
+    }
+
+    /// Returns where an absolute position should be played back.
+    fn place(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        let x = (x as f64 * self.scale.0).round() as i32;
+        let y = (y as f64 * self.scale.1).round() as i32;
+        (x + self.shift.0, y + self.shift.1)
+    }
+
+    /// Checks if the playback has been asked to stop.
+    fn is_stopped(&self) -> bool {
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    /// Computes the offset each action should be played back at.
+    fn offsets(&self, actions: &[MacroAction], end: u64) -> Vec<u64> {
+        let amount = self.humanize.as_micros() as u64;
+        if amount == 0 {
+            return actions.iter().map(|a| a.offset).collect();
+        }
+
+        let mut rng = utils::Rng::new(self.seed);
+        let mut previous = 0;
+        actions.iter()
+            .map(|a| {
+                let shift = rng.below(amount * 2 + 1);
+                let offset = (a.offset + shift).saturating_sub(amount);
+
+                // Keep the action inside the macro, and after the previous one.
+                previous = offset.min(end.saturating_sub(1)).max(previous);
+                previous
+            })
+            .collect()
+    }
+
+    /// Creates options which play a macro recorded from `origin`
+    /// as if it had been recorded from `launch` instead.
+    fn anchored(origin: (i32, i32), launch: (i32, i32)) -> Self {
+        PlaybackOptions {
+            shift: (launch.0 - origin.0, launch.1 - origin.1),
+            ..Default::default()
+        }
+    }
+}
+
+/// How much to randomize a playback, so that no two runs are identical.
+///
+/// Timing jitter shifts each action by a random amount, without
+/// reordering them. Movement jitter makes the cursor stray from the
+/// straight path to each mouse move, while still ending up exactly
+/// where the recorded move did. Both are off by default.
+///
+/// The same seed always produces the same playback.
+#[derive(Debug, Clone, Default)]
+pub struct JitterConfig {
+    timing: Duration,
+    movement: u32,
+    seed: u64
+}
+
+impl JitterConfig {
+    /// Creates a config which doesn't randomize anything yet,
+    /// using `seed` for the random numbers.
+    pub fn new(seed: u64) -> Self {
+        JitterConfig {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// Shifts each action by up to `amount` in either direction.
+    pub fn timing(mut self, amount: Duration) -> Self {
+        self.timing = amount;
+        self
+    }
+
+    /// Strays up to `pixels` in each direction on the way to each mouse move.
+    pub fn movement(mut self, pixels: u32) -> Self {
+        self.movement = pixels;
+        self
+    }
+}
+
+/// Returns the actions to play back, with relative mouse moves scaled from
+/// the display scale factor the macro was recorded at to the `current` one.
+///
+/// Positions are reported in physical pixels, so a move recorded at a
+/// scale of `2.0` covers twice as many pixels as the same move at `1.0`.
+/// Nothing is scaled unless both scale factors are known. Absolute moves
+/// are left alone; `Macro::playback_scaled` maps those to the display.
+fn rescaled<'a>(actions: &'a [MacroAction], metadata: &MacroMetadata, current: Option<f64>) -> Cow<'a, [MacroAction]> {
+    let (Some(recorded), Some(current)) = (metadata.display_scale, current) else {
+        return Cow::Borrowed(actions);
+    };
+    if metadata.mouse_mode != MouseMode::Relative || recorded <= 0.0 || recorded == current {
+        return Cow::Borrowed(actions);
+    }
+
+    // Carry the rounding error so small moves still add up.
+    let factor = current / recorded;
+    let mut sub_pixel = utils::SubPixel::default();
+    let rescaled = actions.iter()
+        .map(|action| match &action.action {
+            UserAction::MouseMove(mouse) => {
+                let (x, y) = sub_pixel.step(mouse.x as f64 * factor, mouse.y as f64 * factor);
+                MacroAction { offset: action.offset, action: UserAction::MouseMove(MouseMoveAction { x, y }) }
+            }
+            _ => action.clone()
+        })
+        .collect();
+
+    Cow::Owned(rescaled)
+}
+
+impl std::ops::AddAssign for PlaybackOutcome {
+    fn add_assign(&mut self, other: Self) {
+        self.dispatched += other.dispatched;
+        self.skipped_keys += other.skipped_keys;
+        self.unknown_buttons += other.unknown_buttons;
+        self.released += other.released;
+        self.drift = other.drift.or(self.drift);
+    }
 }
 
 /// A macro action that includes the type of action and the
-/// offset in time when the action occurred.
+/// offset in time (in microseconds) when the action occurred.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct MacroAction {
-    action: UserAction,
-    offset: u64
+pub(crate) struct MacroAction<K = String> {
+    pub(crate) action: UserAction<K>,
+    pub(crate) offset: u64
 }
 
 pub struct MacroGuard {
     // Store all variables as type-erased boxes
     _guards: Vec<Box<dyn std::any::Any>>,
+    // The recording to stop when the guard is dropped
+    recording: Option<Recording>
 }
 
 /// The `MacroGuard` is used to keep the references to the
@@ -54,10 +794,13 @@ pub struct MacroGuard {
 ///
 /// If the `MacroGuard` instance is dropped, the recording
 /// will stop and the callbacks will no longer be active.
+/// The end of the macro is set as if `stop_recording` was
+/// called, unless it already has been.
 impl MacroGuard {
     fn new() -> Self {
         Self {
             _guards: Vec::new(),
+            recording: None
         }
     }
 
@@ -65,333 +808,2423 @@ impl MacroGuard {
         self._guards.push(Box::new(value));
         self
     }
+
+    /// Stops the given recording when the guard is dropped.
+    fn stops(mut self, recording: Recording) -> Self {
+        self.recording = Some(recording);
+        self
+    }
 }
 
-/// The metadata of a `Macro` includes:
-/// - The end timestamp of the macro
-/// - The initial cursor starting position
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct MacroMetadata {
-    pub(crate) end: u64,
-    pub(crate) cursor_pos: (i32, i32)
+impl Drop for MacroGuard {
+    fn drop(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            recording.stop();
+        }
+    }
 }
 
-/// The `Macro` struct represents a series of actions taken by
-/// the user such as key presses, mouse clicks, and mouse movements.
+/// Whether a macro is being recorded, and a way to wait for it to stop.
+#[derive(Debug, Default)]
+struct RecordingFlag {
+    recording: Mutex<bool>,
+    /// Notified whenever the recording stops.
+    stopped: Condvar
+}
+
+impl RecordingFlag {
+    /// Locks the flag.
+    fn lock(&self) -> MutexGuard<'_, bool> {
+        utils::lock(&self.recording)
+    }
+}
+
+/// The shared state of a macro which is being recorded.
 ///
-/// This struct is serializable and can be deserialized later to
-/// replay actions.
-#[derive(Debug)]
-pub struct Macro {
-    enigo: Enigo,
+/// The references are weak so that a guard doesn't keep
+/// a macro which has been dropped alive.
+#[derive(Clone)]
+struct Recording {
+    /// When the recording started, to tell it apart from later recordings.
+    start: Instant,
+    is_recording: Weak<RecordingFlag>,
+    start_time: Weak<Mutex<Instant>>,
+    metadata: Weak<Mutex<MacroMetadata>>
+}
 
-    start_time: Arc<Mutex<Instant>>,
-    is_recording: Arc<Mutex<bool>>,
+impl Recording {
+    /// Stops the recording, if the macro still exists and is still
+    /// running this recording.
+    fn stop(&self) {
+        let (Some(is_recording), Some(start_time), Some(metadata)) =
+            (self.is_recording.upgrade(), self.start_time.upgrade(), self.metadata.upgrade()) else {
+            return;
+        };
+
+        // A newer recording may have started since this one.
+        if *utils::lock(&start_time) != self.start {
+            return;
+        }
+
+        finish_recording(&is_recording, &start_time, &metadata);
+    }
+
+    /// Stops the recording after `max`, on a timer thread which exits
+    /// early if the recording is stopped sooner.
+    fn stop_after(self, max: Duration) -> Option<JoinHandle<()>> {
+        let is_recording = self.is_recording.upgrade()?;
+
+        Some(std::thread::spawn(move || {
+            let recording = is_recording.lock();
+            let (recording, timeout) = is_recording.stopped
+                .wait_timeout_while(recording, max, |recording| *recording)
+                .unwrap_or_else(PoisonError::into_inner);
+            drop(recording);
+
+            if timeout.timed_out() {
+                self.stop();
+            }
+        }))
+    }
+}
 
+/// The shared state of a macro which device callbacks record into.
+#[derive(Clone)]
+struct Recorder {
+    start: Instant,
     actions: Arc<Mutex<Vec<MacroAction>>>,
+    is_recording: Arc<RecordingFlag>,
+    start_time: Arc<Mutex<Instant>>,
     metadata: Arc<Mutex<MacroMetadata>>
 }
 
-impl Macro {
-    /// Creates a new macro instance.
-    pub fn new() -> Self {
-        Macro {
-            enigo: Enigo::new(&Settings::default()).unwrap(),
-            start_time: Arc::new(Mutex::new(Instant::now())),
-            is_recording: Arc::new(Mutex::new(false)),
-            actions: Arc::new(Mutex::new(vec![])),
-            metadata: Arc::new(Mutex::new(MacroMetadata::default()))
+impl Recorder {
+    /// Records an action which happened now, unless the recording has stopped.
+    fn push(&self, action: UserAction) {
+        let recording = self.is_recording.lock();
+        if !*recording {
+            return;
         }
+
+        utils::lock(&self.actions).push(MacroAction {
+            offset: Instant::now().time_since(self.start),
+            action
+        });
     }
 
-    /// Starts the recording of user actions.
+    /// Stops the recording.
+    fn stop(&self) {
+        finish_recording(&self.is_recording, &self.start_time, &self.metadata);
+    }
+}
+
+/// Marks a recording as stopped and sets the end time of the macro.
+///
+/// Does nothing if the macro isn't being recorded, so that the end
+/// time is only set once.
+fn finish_recording(is_recording: &RecordingFlag, start_time: &Mutex<Instant>, metadata: &Mutex<MacroMetadata>) {
+    let mut recording = is_recording.lock();
+    if !*recording {
+        return;
+    }
+
+    // This will stop any threads from holding on
+    // to the recording state if they use `is_recording()`,
+    // and wake any threads in `wait_until_stopped()`.
+    *recording = false;
+    is_recording.stopped.notify_all();
+
+    // Set the end time of the macro.
+    let start_time = *utils::lock(start_time);
+    utils::lock(metadata).end = Instant::now().time_since(start_time);
+}
+
+/// The default interval between device polls while recording.
+pub(crate) const DEFAULT_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// The minimum time between progress reports during playback.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The default longest time to sleep between checks for due actions
+/// while playing back.
+const MAX_PLAYBACK_SLEEP: Duration = Duration::from_millis(10);
+
+/// The longest run of mouse moves which `Macro::optimize` merges into one.
+const COALESCE_WINDOW: Duration = Duration::from_millis(10);
+
+/// The version of the serialized macro format.
+///
+/// - Version 1 stored offsets in milliseconds and had no version field.
+/// - Version 2 stores offsets in microseconds.
+///
+/// Older versions are upgraded when loaded, and newer ones are rejected.
+pub(crate) const FORMAT_VERSION: u32 = 2;
+
+/// The bytes every gzip stream starts with, used to recognize compressed macros.
+#[cfg(feature = "compress")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How mouse movements are stored in a macro.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseMode {
+    /// Each move stores the cursor's position on screen,
+    /// and is played back by moving straight to it.
+    ///
+    /// Macros recorded before the mode was stored use this mode.
+    #[default]
+    Absolute,
+    /// Each move stores the distance from the previous position,
+    /// and is played back by moving the cursor by that distance.
+    ///
+    /// Relative moves keep working when the screen layout changes,
+    /// but a dropped or clamped move shifts every move after it.
+    Relative
+}
+
+/// What to do when a macro is played back in a different window
+/// than the one it was recorded in.
+///
+/// Windows are compared by title, which is only recorded when the
+/// `window-title` feature is enabled; without it, or for macros
+/// with no recorded title, the window is never checked.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WindowCheck {
+    /// Play the macro back in any window.
+    #[default]
+    Ignore,
+    /// Log a warning, but play the macro back anyway.
+    Warn,
+    /// Don't play the macro back, returning `PlaybackError::WrongWindow`.
+    Require
+}
+
+/// The metadata of a `Macro` includes:
+/// - The end timestamp of the macro
+/// - The initial cursor starting position
+/// - How mouse movements are stored
+/// - The keys which were already held down when recording started
+/// - The size of the main display it was recorded on, if known
+/// - The title of the window it was recorded in, if known
+/// - The scale factor of the display it was recorded on, if known
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct MacroMetadata {
+    pub(crate) end: u64,
+    pub(crate) cursor_pos: (i32, i32),
+    #[serde(default)]
+    pub(crate) mouse_mode: MouseMode,
+    #[serde(default)]
+    pub(crate) held_keys: Vec<String>,
+    #[serde(default)]
+    pub(crate) screen_size: Option<(i32, i32)>,
+    #[serde(default)]
+    pub(crate) window_title: Option<String>,
+    #[serde(default)]
+    pub(crate) display_scale: Option<f64>
+}
+
+impl MacroMetadata {
+    /// Returns where the cursor is after playing `actions`,
+    /// starting from the initial cursor position.
+    pub(crate) fn cursor_after(&self, actions: &[MacroAction]) -> (i32, i32) {
+        let mut moves = actions.iter().filter_map(|a| match &a.action {
+            UserAction::MouseMove(mouse) => Some((mouse.x, mouse.y)),
+            _ => None
+        });
+
+        match self.mouse_mode {
+            MouseMode::Absolute => moves.next_back().unwrap_or(self.cursor_pos),
+            MouseMode::Relative => moves.fold(self.cursor_pos, |(x, y), (dx, dy)| (x + dx, y + dy))
+        }
+    }
+
+    /// Returns the keys which are held down after playing `actions`,
+    /// starting with the keys held when recording started.
+    pub(crate) fn keys_after(&self, actions: &[MacroAction]) -> Vec<String> {
+        let mut held = self.held_keys.clone();
+        for action in actions {
+            if let UserAction::Key(key) = &action.action {
+                held.retain(|k| *k != key.key);
+                if key.pressed {
+                    held.push(key.key.clone());
+                }
+            }
+        }
+
+        held
+    }
+}
+
+/// The `Macro` struct represents a series of actions taken by
+/// the user such as key presses, mouse clicks, and mouse movements.
+///
+/// This struct is serializable and can be deserialized later to
+/// replay actions.
+///
+/// The `Enigo` instance used to play actions back is only created
+/// when it's first needed, so macros which are cloned or deserialized
+/// never need a display until they're played back.
+///
+/// Clones of a macro share its actions and recording state, so that
+/// a clone can record on another thread while the original stops it.
+/// Use `Macro::deep_copy` for a copy which can be changed on its own.
+#[derive(Debug)]
+pub struct Macro {
+    pub(crate) enigo: Option<Enigo>,
+    pub(crate) settings: Settings,
+    pub(crate) poll_interval: Duration,
+    pub(crate) remap: RemapTable,
+    /// The scale factor of the display, if it was set instead of detected.
+    pub(crate) display_scale: Option<f64>,
+
+    start_time: Arc<Mutex<Instant>>,
+    is_recording: Arc<RecordingFlag>,
+
+    pub(crate) actions: Arc<Mutex<Vec<MacroAction>>>,
+    pub(crate) metadata: Arc<Mutex<MacroMetadata>>
+}
+
+impl Macro {
+    /// Creates a new macro instance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a connection to the input system can't be made,
+    /// such as on a headless machine. Use `try_new` to handle this.
+    pub fn new() -> Self {
+        Macro::try_new().expect("Failed to connect to the input system")
+    }
+
+    /// Creates a new macro instance, connecting to the input system.
+    pub fn try_new() -> Result<Self, NewConError> {
+        let mut new = MacroBuilder::new().build();
+        new.connect()?;
+        Ok(new)
+    }
+
+    /// Creates a new macro instance which plays back using `enigo`.
+    ///
+    /// The connection is only used by this macro; clones and macros
+    /// playing back on other threads, such as with `playback_async`,
+    /// make their own connection using the default settings. Use
+    /// `MacroBuilder::enigo_settings` and `Macro::set_enigo` instead
+    /// if they should share the same settings.
+    pub fn with_enigo(enigo: Enigo) -> Self {
+        let mut new = Macro::from_parts(Vec::new(), MacroMetadata::default());
+        new.enigo = Some(enigo);
+        new
+    }
+
+    /// Replaces the connection this macro plays back with, returning the
+    /// previous one, if it had connected.
+    pub fn set_enigo(&mut self, enigo: Enigo) -> Option<Enigo> {
+        self.enigo.replace(enigo)
+    }
+
+    /// Takes the connection this macro plays back with, if it has connected.
+    ///
+    /// The macro makes a new connection the next time it's played back.
+    pub fn take_enigo(&mut self) -> Option<Enigo> {
+        self.enigo.take()
+    }
+
+    /// Creates a builder for configuring a new macro.
+    pub fn builder() -> MacroBuilder {
+        MacroBuilder::new()
+    }
+
+    /// Creates a macro instance from existing actions and metadata.
+    pub(crate) fn from_parts(actions: Vec<MacroAction>, metadata: MacroMetadata) -> Self {
+        Macro {
+            enigo: None,
+            settings: Settings::default(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            remap: RemapTable::new(),
+            display_scale: None,
+            start_time: Arc::new(Mutex::new(Instant::now())),
+            is_recording: Arc::new(RecordingFlag::default()),
+            actions: Arc::new(Mutex::new(actions)),
+            metadata: Arc::new(Mutex::new(metadata))
+        }
+    }
+
+    /// Starts the recording of user actions.
     ///
     /// The returned guard must be held to keep the recording active.
     pub fn record(&self) -> MacroGuard {
+        self.record_with_interval(self.poll_interval)
+    }
+
+    /// Starts the recording of user actions, polling devices at the given interval.
+    ///
+    /// Shorter intervals capture input timing more precisely, but keep
+    /// a CPU core busier. Longer intervals are lighter, but events which
+    /// happen within one interval of each other get the same offset.
+    ///
+    /// `device_query` polls devices on a single, process-wide thread
+    /// which is started by the first recording. Later recordings reuse
+    /// it, so only the first interval used in a process takes effect.
+    ///
+    /// The returned guard must be held to keep the recording active.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn record_with_interval(&self, interval: Duration) -> MacroGuard {
+        self.start_recording(interval, false, RecordFilter::ALL, None)
+    }
+
+    /// Starts the recording of only the kinds of user actions in `filter`.
+    ///
+    /// Devices which aren't recorded aren't listened to at all,
+    /// which keeps recordings which only need the keyboard small.
+    ///
+    /// The returned guard must be held to keep the recording active.
+    pub fn record_filtered(&self, filter: RecordFilter) -> MacroGuard {
+        self.start_recording(self.poll_interval, false, filter, None)
+    }
+
+    /// Starts the recording of user actions, stopping after `max` at the latest.
+    ///
+    /// The recording is stopped by a timer thread, which finishes as soon
+    /// as the recording stops, whether that's because of the timer, the
+    /// guard being dropped, or `stop_recording`.
+    ///
+    /// If `max` is zero, no recording is started; the macro is left empty,
+    /// with a length of zero, and keeps only its mouse mode.
+    ///
+    /// The returned guard must be held to keep the recording active.
+    pub fn record_for(&self, max: Duration) -> MacroGuard {
+        if max.is_zero() {
+            self.stop_recording();
+            utils::lock(&self.actions).clear();
+            let mut metadata = utils::lock(&self.metadata);
+            *metadata = MacroMetadata { mouse_mode: metadata.mouse_mode, ..Default::default() };
+            return MacroGuard::new();
+        }
+
+        let guard = self.record();
+        match guard.recording.clone().and_then(|recording| recording.stop_after(max)) {
+            Some(timer) => guard.keep_alive(timer),
+            None => guard
+        }
+    }
+
+    /// Starts the recording of user actions, stopping when `stop_key` is pressed.
+    ///
+    /// Pressing and releasing the stop key isn't recorded. This doesn't
+    /// block; the returned guard must still be held to keep the recording
+    /// active, and `wait_until_stopped` can be used to wait for the stop
+    /// key to be pressed.
+    pub fn record_until_key(&self, stop_key: Keycode) -> MacroGuard {
+        self.start_recording(self.poll_interval, false, RecordFilter::ALL, Some(stop_key))
+    }
+
+    /// Continues the recording of user actions after the existing ones.
+    ///
+    /// New actions are placed after the end of this macro, so a macro
+    /// can be recorded in several parts with pauses in between.
+    /// The initial cursor position is only set by `record`; in relative
+    /// mode, moves made between recordings aren't part of the macro.
+    ///
+    /// The returned guard must be held to keep the recording active.
+    pub fn record_append(&self) -> MacroGuard {
+        self.start_recording(self.poll_interval, true, RecordFilter::ALL, None)
+    }
+
+    /// Starts the recording of the user actions in `filter`,
+    /// keeping the existing actions if `append` is set, and
+    /// stopping when `stop_key` is pressed, if one is given.
+    fn start_recording(&self, interval: Duration, append: bool, filter: RecordFilter, stop_key: Option<Keycode>) -> MacroGuard {
+        assert!(!interval.is_zero(), "polling interval must be non-zero");
+
         // Mark state as recording.
-        *self.is_recording.lock().unwrap() = true;
+        *self.is_recording.lock() = true;
+
+        // Clear existing actions, unless they're being added to.
+        let prior = if append {
+            Duration::from_micros(utils::lock(&self.metadata).end)
+        } else {
+            utils::lock(&self.actions).clear();
+            Duration::ZERO
+        };
+
+        // Mark the start of the recording; when appending, it's moved
+        // back so that new actions are offset by the existing length.
+        let now = Instant::now();
+        let start = now.checked_sub(prior).unwrap_or(now);
+        *utils::lock(&self.start_time) = start;
+
+        // Reuse the polling thread if a previous recording started it.
+        let listener = DeviceEventsHandler::new(interval).unwrap_or(DeviceEventsHandler);
+
+        // Set the starting cursor position.
+        let state = DeviceState::new();
+        let (x, y) = state.get_mouse().coords;
+        // Store the initial cursor position and any keys which are
+        // already held down in the metadata, since no events will be
+        // reported for pressing them.
+        if !append {
+            let screen_size = self.display_size();
+            let mut metadata = utils::lock(&self.metadata);
+            metadata.cursor_pos = (x, y);
+            metadata.screen_size = screen_size;
+            metadata.window_title = utils::window_title();
+            metadata.display_scale = self.current_display_scale();
+            metadata.held_keys = if filter.contains(RecordFilter::KEYS) {
+                state.get_keys().iter().map(utils::to_string).collect()
+            } else {
+                Vec::new()
+            };
+        }
+
+        let mouse_mode = utils::lock(&self.metadata).mouse_mode;
+        let recorder = Recorder {
+            start,
+            actions: self.actions.clone(),
+            is_recording: self.is_recording.clone(),
+            start_time: self.start_time.clone(),
+            metadata: self.metadata.clone()
+        };
+        let mut guard = MacroGuard::new();
+
+        // Start listening for the enabled device events.
+        if filter.contains(RecordFilter::KEYS) || stop_key.is_some() {
+            let key_up = recorder.clone();
+            let key_down = recorder.clone();
+            let record_keys = filter.contains(RecordFilter::KEYS);
+
+            guard = guard.keep_alive(listener.on_key_up(move |key| {
+                // Record the key up action.
+                if record_keys && Some(*key) != stop_key {
+                    key_up.push(UserAction::Key(KeyAction { key: utils::to_string(key), pressed: false }));
+                }
+            }));
+
+            guard = guard.keep_alive(listener.on_key_down(move |key| {
+                // Stop recording if the stop key was pressed.
+                if Some(*key) == stop_key {
+                    key_down.stop();
+                } else if record_keys {
+                    // Record the key down action.
+                    key_down.push(UserAction::Key(KeyAction { key: utils::to_string(key), pressed: true }));
+                }
+            }));
+        }
+
+        if filter.contains(RecordFilter::MOUSE_BUTTONS) {
+            let mouse_up = recorder.clone();
+            let mouse_down = recorder.clone();
+
+            guard = guard.keep_alive(listener.on_mouse_up(move |button| {
+                // Record the mouse button up action.
+                mouse_up.push(UserAction::MouseButton(MouseButtonAction { button: *button, pressed: false }));
+            }));
+
+            guard = guard.keep_alive(listener.on_mouse_down(move |button| {
+                // Record the mouse button down action.
+                mouse_down.push(UserAction::MouseButton(MouseButtonAction { button: *button, pressed: true }));
+            }));
+        }
+
+        // Scroll events aren't reported by `device_query`, so they can't be
+        // recorded; `MouseScroll` actions only come from edited macros.
+        if filter.contains(RecordFilter::MOUSE_MOVE) {
+            let mouse_move = recorder.clone();
+            let last_pos = Mutex::new((x, y));
+
+            guard = guard.keep_alive(listener.on_mouse_move(move |position| {
+                // Record the mouse move action.
+                let (x, y) = match mouse_mode {
+                    MouseMode::Absolute => *position,
+                    MouseMode::Relative => {
+                        let mut last_pos = utils::lock(&last_pos);
+                        let delta = (position.0 - last_pos.0, position.1 - last_pos.1);
+                        *last_pos = *position;
+                        delta
+                    }
+                };
+                mouse_move.push(UserAction::MouseMove(MouseMoveAction { x, y }));
+            }));
+        }
+
+        guard.stops(Recording {
+            start,
+            is_recording: Arc::downgrade(&self.is_recording),
+            start_time: Arc::downgrade(&self.start_time),
+            metadata: Arc::downgrade(&self.metadata)
+        })
+    }
+
+    /// Returns the scale factor of the display, if it was set or can be found.
+    fn current_display_scale(&self) -> Option<f64> {
+        self.display_scale.or_else(utils::display_scale)
+    }
+
+    /// Returns the size of the main display, if it can be found.
+    fn display_size(&self) -> Option<(i32, i32)> {
+        match &self.enigo {
+            Some(enigo) => enigo.main_display().ok(),
+            None => Enigo::new(&self.settings).ok()?.main_display().ok()
+        }
+    }
+
+    /// Stops the macro recording.
+    ///
+    /// Actions aren't recorded after this, even if the recording's
+    /// guard is still held. Calling this more than once, or dropping
+    /// the guard afterwards, doesn't change the end of the macro again.
+    pub fn stop_recording(&self) {
+        finish_recording(&self.is_recording, &self.start_time, &self.metadata);
+    }
+
+    /// Checks if a macro is currently being recorded.
+    pub fn is_recording(&self) -> bool {
+        *self.is_recording.lock()
+    }
+
+    /// Blocks the current thread until the macro stops being recorded.
+    ///
+    /// Returns immediately if the macro isn't being recorded.
+    pub fn wait_until_stopped(&self) {
+        let recording = self.is_recording.lock();
+        drop(self.is_recording.stopped.wait_while(recording, |recording| *recording)
+            .unwrap_or_else(PoisonError::into_inner));
+    }
+
+    /// Returns the table used to look up keys during playback.
+    ///
+    /// Keys can be added to the table to play back keys which
+    /// have no built-in mapping, or to swap keys for others.
+    pub fn remap_mut(&mut self) -> &mut RemapTable {
+        &mut self.remap
+    }
+
+    /// Returns how long this macro takes to play back at normal speed.
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(utils::lock(&self.metadata).end)
+    }
+
+    /// Returns the title of the window this macro was recorded in, if known.
+    ///
+    /// Titles are only recorded when the `window-title` feature is enabled.
+    pub fn window_title(&self) -> Option<String> {
+        utils::lock(&self.metadata).window_title.clone()
+    }
+
+    /// Returns how mouse movements are stored in this macro.
+    pub fn mouse_mode(&self) -> MouseMode {
+        utils::lock(&self.metadata).mouse_mode
+    }
+
+    /// Plays any stored macro actions.
+    ///
+    /// This method will block until all actions have been played back.
+    ///
+    /// Actions which cannot be played back (such as keys without a
+    /// mapping) are skipped, and tallied in the returned outcome.
+    ///
+    /// Any keys or mouse buttons which are still pressed when the
+    /// playback ends are released, even if the playback fails.
+    pub fn playback(&mut self) -> Result<PlaybackOutcome, PlaybackError> {
+        self.playback_with(&PlaybackOptions::default())
+    }
+
+    /// Plays any stored macro actions, panicking if the playback fails.
+    ///
+    /// This method will block until all actions have been played back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a connection to the input system can't be made,
+    /// or the input system fails to perform an action.
+    pub fn playback_or_panic(&mut self) -> PlaybackOutcome {
+        self.playback().expect("Failed to play back macro")
+    }
+
+    /// Plays any stored macro actions at the given speed.
+    ///
+    /// A `speed` of `2.0` plays the macro twice as fast, and `0.5` plays
+    /// it at half speed. The cursor is still moved to its starting
+    /// position immediately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is not a positive, finite number.
+    pub fn playback_with_speed(&mut self, speed: f64) -> Result<PlaybackOutcome, PlaybackError> {
+        self.playback_with(&PlaybackOptions::new().speed(speed))
+    }
+
+    /// Plays any stored macro actions, splitting each mouse move into
+    /// `steps` smaller moves so that the cursor glides between positions.
+    ///
+    /// See `PlaybackOptions::smooth` for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `steps` is zero.
+    pub fn playback_smooth(&mut self, steps: u32) -> Result<PlaybackOutcome, PlaybackError> {
+        self.playback_with(&PlaybackOptions::new().smooth(steps))
+    }
+
+    /// Plays any stored macro actions, scaling mouse positions from the
+    /// display the macro was recorded on to the current main display.
+    ///
+    /// Only absolute mouse positions are scaled. Relative moves are
+    /// distances which pointer acceleration and screen edges already
+    /// distort, so they're played back as recorded. Macros which don't
+    /// know their display size, such as ones recorded by older versions,
+    /// are played back unscaled.
+    ///
+    /// This method will block until all actions have been played back.
+    pub fn playback_scaled(&mut self) -> Result<PlaybackOutcome, PlaybackError> {
+        let current = self.connect()?.main_display().ok();
+        let recorded = utils::lock(&self.metadata).screen_size;
+
+        let options = match (recorded, current) {
+            (Some((width, height)), Some((to_width, to_height))) if width > 0 && height > 0 => {
+                PlaybackOptions::new().scale(to_width as f64 / width as f64, to_height as f64 / height as f64)
+            }
+            _ => PlaybackOptions::new()
+        };
+
+        self.playback_with(&options)
+    }
+
+    /// Plays any stored macro actions `times` times in a row.
+    ///
+    /// The cursor is moved back to its starting position at the start
+    /// of every repetition. The returned outcome covers all repetitions.
+    /// If a repetition fails, the rest aren't played.
+    pub fn playback_repeat(&mut self, times: usize) -> Result<PlaybackOutcome, PlaybackError> {
+        let options = PlaybackOptions::default();
+        let mut outcome = PlaybackOutcome::default();
+        for _ in 0..times {
+            outcome += self.playback_with(&options)?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Plays any stored macro actions over and over, until `stop` is set.
+    ///
+    /// The flag is checked as often as during any other playback, so
+    /// setting it from another thread stops the current repetition part
+    /// of the way through and releases anything it was holding down.
+    /// The returned outcome covers all repetitions. If a repetition
+    /// fails, the rest aren't played.
+    pub fn playback_loop(&mut self, stop: Arc<AtomicBool>) -> Result<PlaybackOutcome, PlaybackError> {
+        let options = PlaybackOptions {
+            stop: Some(stop),
+            ..Default::default()
+        };
+
+        let mut outcome = PlaybackOutcome::default();
+        while !options.is_stopped() {
+            outcome += self.playback_with(&options)?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Plays any stored macro actions relative to the current cursor position.
+    ///
+    /// The cursor position at the start of the recording is treated as
+    /// the origin of the macro. Instead of warping the cursor back to it,
+    /// every mouse movement is shifted by the distance between the origin
+    /// and the current cursor position.
+    ///
+    /// This method will block until all actions have been played back.
+    pub fn playback_here(&mut self) -> Result<PlaybackOutcome, PlaybackError> {
+        let origin = utils::lock(&self.metadata).cursor_pos;
+        let launch = DeviceState::new().get_mouse().coords;
+
+        self.playback_with(&PlaybackOptions::anchored(origin, launch))
+    }
+
+    /// Types `text` straight away, without recording or playing anything back.
+    ///
+    /// Newlines are typed with the return key, and tabs with the tab key.
+    ///
+    /// Returns an error if a connection to the input system can't be
+    /// made, or the input system fails to type the text.
+    pub fn type_text(&mut self, text: &str) -> Result<(), PlaybackError> {
+        let enigo = self.connect()?;
+
+        // Type everything between the control characters as text.
+        let mut rest = text;
+        while let Some(index) = rest.find(['\n', '\t']) {
+            if index > 0 {
+                enigo.text(&rest[..index])?;
+            }
+
+            let key = if rest[index..].starts_with('\n') { Key::Return } else { Key::Tab };
+            enigo.key(key, Direction::Click)?;
+            rest = &rest[index + 1..];
+        }
+        if !rest.is_empty() {
+            enigo.text(rest)?;
+        }
+
+        Ok(())
+    }
+
+    /// Plays any stored macro actions with the given options.
+    ///
+    /// This method will block until all actions have been played back.
+    pub fn playback_with(&mut self, options: &PlaybackOptions) -> Result<PlaybackOutcome, PlaybackError> {
+        self.play_actions(options, |_| {}, |_, _| {})
+    }
+
+    /// Plays any stored macro actions, reporting how far along the playback is.
+    ///
+    /// `on_progress` is called with the fraction of the macro which has
+    /// been played, from `0.0` to `1.0`, every few milliseconds. It's
+    /// always called with `1.0` once the macro has finished.
+    ///
+    /// This method will block until all actions have been played back.
+    pub fn playback_with_progress(&mut self, on_progress: impl FnMut(f64)) -> Result<PlaybackOutcome, PlaybackError> {
+        self.play_actions(&PlaybackOptions::default(), on_progress, |_, _| {})
+    }
+
+    /// Plays any stored macro actions, sending an event to `tx` as each step happens.
+    ///
+    /// If the receiver is dropped, the playback carries on without sending
+    /// any more events. If the playback fails, no `Finished` event is sent.
+    ///
+    /// This method will block until all actions have been played back.
+    pub fn playback_with_events(&mut self, tx: Sender<PlaybackEvent>) -> Result<PlaybackOutcome, PlaybackError> {
+        let mut connected = tx.send(PlaybackEvent::Started).is_ok();
+        let remap = self.remap.clone();
+
+        let outcome = self.play_actions(&PlaybackOptions::default(), |_| {}, |action, offset| {
+            if connected {
+                let view = ActionView::new(action, &remap);
+                connected = tx.send(PlaybackEvent::Action { view, offset }).is_ok();
+            }
+        })?;
+
+        if connected {
+            tx.send(PlaybackEvent::Finished(outcome)).ok();
+        }
+
+        Ok(outcome)
+    }
+
+    /// Plays any stored macro actions with the given options,
+    /// reporting progress to `on_progress` and each action
+    /// played, along with when it was played, to `on_action`.
+    ///
+    /// If the playback is stopped early, the last progress reported
+    /// is how far it got rather than `1.0`. If it fails, everything
+    /// held down is still released before the error is returned.
+    fn play_actions(
+        &mut self,
+        options: &PlaybackOptions,
+        mut on_progress: impl FnMut(f64),
+        mut on_action: impl FnMut(&MacroAction, Duration)
+    ) -> Result<PlaybackOutcome, PlaybackError> {
+        let mut outcome = PlaybackOutcome::default();
+        let start = Instant::now();
+        // Time spent pausing after clicks, which doesn't count towards offsets.
+        let mut paused = Duration::ZERO;
+        // Play a copy of the macro, so that it can be inspected and
+        // edited from other threads while it plays.
+        let metadata = utils::lock(&self.metadata).clone();
+        let actions = utils::lock(&self.actions).clone();
+        options.check_focused_window(&metadata)?;
+        self.connect()?;
+        let enigo = self.enigo.as_mut().unwrap();
+        let actions = rescaled(&actions, &metadata, self.display_scale.or_else(utils::display_scale));
+        let actions = options.wandered(options.smoothed(&actions, &metadata), &metadata);
+        let offsets = options.offsets(&actions, metadata.end);
+
+        // Play the actions back in time order; a stable sort keeps
+        // actions which share an offset in the order they were recorded.
+        let mut order = (0..actions.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| offsets[*i]);
+        let mut next = 0;
+        let mut held = Held::default();
+
+        let mut last_progress: Option<Instant> = None;
+        let progress = |offset: u64| match metadata.end {
+            0 => 1.0,
+            end => (offset as f64 / end as f64).min(1.0)
+        };
+
+        let mut play = || -> Result<(), InputError> {
+            // Move the cursor to the initial position.
+            let mut expected = options.place(metadata.cursor_pos);
+            if options.shift == (0, 0) {
+                enigo.move_mouse(expected.0, expected.1, Coordinate::Abs)?;
+            } else if metadata.mouse_mode == MouseMode::Relative {
+                expected = DeviceState::new().get_mouse().coords;
+            }
+
+            // Press the keys which were held when the recording started.
+            // They're released along with everything else at the end.
+            for key in &metadata.held_keys {
+                let action = UserAction::Key(KeyAction { key: key.clone(), pressed: true });
+                action.play(enigo, metadata.mouse_mode, &self.remap, options, &mut held, &mut outcome)?;
+            }
+
+            loop {
+                // Stop early if asked to.
+                if options.is_stopped() {
+                    break;
+                }
+
+                let elapsed = (Instant::now() - paused).duration_since(start);
+                let offset = elapsed.mul_f64(options.speed).as_micros() as u64;
+
+                // Play every action which is due.
+                while let Some(&index) = order.get(next)
+                    && offsets[index] <= offset {
+                    let action = &actions[index].action;
+                    action.play(enigo, metadata.mouse_mode, &self.remap, options, &mut held, &mut outcome)?;
+                    on_action(&actions[index], Duration::from_micros(offset));
+                    next += 1;
+
+                    // Keep track of where relative moves should leave the cursor.
+                    if let UserAction::MouseMove(mouse) = action
+                        && metadata.mouse_mode == MouseMode::Relative {
+                        expected = (expected.0 + mouse.x, expected.1 + mouse.y);
+                    }
+
+                    // Give the application time to handle the click.
+                    if action.is_release() && !options.post_click_delay.is_zero() {
+                        sleep(options.post_click_delay);
+                        paused += options.post_click_delay;
+                    }
+                }
+
+                // Check if the macro is over.
+                if offset >= metadata.end {
+                    // Stop playback if the end time has been reached.
+                    on_progress(1.0);
+                    break;
+                }
+
+                // Report progress every so often.
+                if last_progress.is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL) {
+                    on_progress(progress(offset));
+                    last_progress = Some(Instant::now());
+                }
+
+                // Sleep until the next action is due, or the macro ends, but
+                // wake up in time to notice being stopped. Offsets are always
+                // measured from the start, so oversleeping doesn't add up.
+                let due = order.get(next)
+                    .map_or(metadata.end, |&index| offsets[index].min(metadata.end));
+                let remaining = Duration::from_micros(due.saturating_sub(offset)).div_f64(options.speed);
+                sleep(remaining.min(options.poll_interval));
+            }
+
+            // Compare where relative moves left the cursor with where they should have.
+            if metadata.mouse_mode == MouseMode::Relative {
+                let actual = DeviceState::new().get_mouse().coords;
+                outcome.drift = Some((actual.0 - expected.0, actual.1 - expected.1));
+
+                if options.correct_drift && actual != expected {
+                    enigo.move_mouse(expected.0, expected.1, Coordinate::Abs)?;
+                }
+            }
+
+            Ok(())
+        };
+        let played = play();
+
+        // Don't leave anything pressed, even if the macro never released it
+        // or the playback failed part of the way through.
+        outcome.released = held.release_all(enigo);
+
+        played?;
+        Ok(outcome)
+    }
+
+    /// Connects to the input system, if this macro hasn't already.
+    fn connect(&mut self) -> Result<&mut Enigo, NewConError> {
+        if self.enigo.is_none() {
+            self.enigo = Some(Enigo::new(&self.settings)?);
+        }
+
+        Ok(self.enigo.as_mut().unwrap())
+    }
+
+    /// Plays any stored macro actions one at a time, when asked to.
+    ///
+    /// The cursor is moved to its starting position, and the keys held
+    /// when the recording started are pressed, straight away. After that,
+    /// each action is played as soon as the returned cursor is advanced,
+    /// however long ago it was recorded. The actions are copied, so the
+    /// macro can be inspected and edited while stepping through it.
+    pub fn step_playback(&mut self) -> Result<PlaybackCursor<'_>, PlaybackError> {
+        let metadata = utils::lock(&self.metadata).clone();
+        let actions = rescaled(&utils::lock(&self.actions), &metadata, self.current_display_scale()).into_owned();
+        self.connect()?;
+
+        let mut cursor = PlaybackCursor {
+            enigo: self.enigo.as_mut().unwrap(),
+            remap: &self.remap,
+            actions,
+            mouse_mode: metadata.mouse_mode,
+            options: PlaybackOptions::default(),
+            next: 0,
+            held: Held::default(),
+            outcome: PlaybackOutcome::default()
+        };
+
+        // Move the cursor to the initial position.
+        let (x, y) = metadata.cursor_pos;
+        cursor.enigo.move_mouse(x, y, Coordinate::Abs)?;
+
+        // Press the keys which were held when the recording started.
+        for key in metadata.held_keys {
+            let action = UserAction::Key(KeyAction { key, pressed: true });
+            action.play(cursor.enigo, cursor.mouse_mode, cursor.remap, &cursor.options, &mut cursor.held, &mut cursor.outcome)?;
+        }
+
+        Ok(cursor)
+    }
+
+    /// Plays any stored macro actions from `start` onwards.
+    ///
+    /// The cursor is first moved to where it would be at `start`. In
+    /// absolute mode that's the last position moved to before `start`;
+    /// in relative mode it's the starting position plus every move
+    /// before `start`. Keys and buttons pressed before `start` aren't
+    /// pressed, so releasing them afterwards has no effect.
+    ///
+    /// This method will block until all actions have been played back.
+    pub fn playback_from(&mut self, start: Duration) -> Result<PlaybackOutcome, PlaybackError> {
+        let mut rest = self.seek(start);
+        rest.enigo = self.enigo.take();
+
+        let outcome = rest.playback();
+        self.enigo = rest.enigo.take();
+
+        outcome
+    }
+
+    /// Returns the part of this macro from `start` onwards, starting where
+    /// the cursor is at `start` and configured the same way as this macro.
+    fn seek(&self, start: Duration) -> Macro {
+        let (_, mut rest) = self.split_at(start);
+        rest.settings = self.settings.clone();
+        rest.poll_interval = self.poll_interval;
+        rest.remap = self.remap.clone();
+        rest.display_scale = self.display_scale;
+
+        rest
+    }
+
+    /// Plays any stored macro actions on a separate thread.
+    ///
+    /// The returned handle can be used to stop the playback early,
+    /// or to wait for it to finish. The playback shares its actions
+    /// with this macro, and connects to the input system separately.
+    pub fn playback_async(&self) -> PlaybackHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let options = PlaybackOptions {
+            stop: Some(stop.clone()),
+            ..Default::default()
+        };
+
+        // The input system connection isn't always `Send`, so the
+        // playback thread makes its own.
+        let settings = self.settings.clone();
+        let poll_interval = self.poll_interval;
+        let remap = self.remap.clone();
+        let display_scale = self.display_scale;
+        let start_time = self.start_time.clone();
+        let is_recording = self.is_recording.clone();
+        let actions = self.actions.clone();
+        let metadata = self.metadata.clone();
+        let thread = std::thread::spawn(move || {
+            let mut towa = Macro {
+                enigo: None,
+                settings,
+                poll_interval,
+                remap,
+                display_scale,
+                start_time,
+                is_recording,
+                actions,
+                metadata
+            };
+            towa.playback_with(&options)
+        });
+
+        PlaybackHandle { stop, thread }
+    }
+
+    /// Plays this macro back `count` times in a row, humanizing each run.
+    ///
+    /// Every run shifts the timing of its actions by up to `humanize`,
+    /// using its own seed. The seeds are returned so that a batch can
+    /// be logged and reproduced with `playback_batch_seeded`. If a run
+    /// fails, the rest aren't played.
+    pub fn playback_batch(&mut self, count: usize, humanize: Duration) -> Result<Vec<u64>, PlaybackError> {
+        let base_seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+
+        self.playback_batch_seeded(count, humanize, base_seed)
+    }
+
+    /// Plays this macro back `count` times in a row, humanizing each run.
+    ///
+    /// The seed of each run is derived from `base_seed`, so the same
+    /// base seed always produces the same batch.
+    pub fn playback_batch_seeded(&mut self, count: usize, humanize: Duration, base_seed: u64) -> Result<Vec<u64>, PlaybackError> {
+        let seeds = Macro::batch_seeds(base_seed, count);
+        for seed in seeds.iter() {
+            self.playback_with(&PlaybackOptions::new().humanize(humanize, *seed))?;
+        }
+
+        Ok(seeds)
+    }
+
+    /// Returns the seeds used for each run of a batch with the given base seed.
+    pub fn batch_seeds(base_seed: u64, count: usize) -> Vec<u64> {
+        let mut rng = utils::Rng::new(base_seed);
+        (0..count).map(|_| rng.next_u64()).collect()
+    }
+
+    /// Plays any stored macro actions with randomized timing and mouse movement.
+    ///
+    /// Actions are never reordered, so every key press is still
+    /// followed by its release.
+    ///
+    /// This method will block until all actions have been played back.
+    pub fn playback_with_jitter(&mut self, config: JitterConfig) -> Result<PlaybackOutcome, PlaybackError> {
+        self.playback_with(&PlaybackOptions::new().jitter(config))
+    }
+
+    /// Creates an independent copy of this macro.
+    ///
+    /// Unlike `clone`, the copy has its own actions and metadata, taken
+    /// from this macro as they are now, so editing or recording into
+    /// either macro doesn't change the other. The copy isn't recording,
+    /// even if this macro is.
+    pub fn deep_copy(&self) -> Macro {
+        let actions = utils::lock(&self.actions).clone();
+        let metadata = utils::lock(&self.metadata).clone();
+
+        let mut copy = Macro::from_parts(actions, metadata);
+        copy.settings = self.settings.clone();
+        copy.poll_interval = self.poll_interval;
+        copy.remap = self.remap.clone();
+        copy.display_scale = self.display_scale;
+
+        copy
+    }
+
+    /// Adds the actions of `other` to the end of this macro.
+    ///
+    /// The cursor is moved to where `other` starts when it begins,
+    /// just as it would be when playing `other` by itself. If the two
+    /// macros store mouse movements differently, the moves of `other`
+    /// are converted to the mouse mode of this macro.
+    pub fn append_macro(&mut self, other: &Macro) {
+        // Copy the other macro first, in case it shares actions with this one.
+        let other_actions = utils::lock(&other.actions).clone();
+        let other_metadata = utils::lock(&other.metadata).clone();
+
+        let mut actions = utils::lock(&self.actions);
+        let mut metadata = utils::lock(&self.metadata);
+        let offset = metadata.end;
+        let mode = metadata.mouse_mode;
+
+        // Moves are converted by tracking where the cursor is in both macros.
+        let mut position = metadata.cursor_after(&actions);
+        let mut other_position = other_metadata.cursor_pos;
+        let mut move_to = |(x, y): (i32, i32)| {
+            let mouse = match mode {
+                MouseMode::Absolute => MouseMoveAction { x, y },
+                MouseMode::Relative => MouseMoveAction { x: x - position.0, y: y - position.1 }
+            };
+            position = (x, y);
+            UserAction::MouseMove(mouse)
+        };
+
+        // Move to where the other macro starts, and press the keys it starts with.
+        actions.push(MacroAction { offset, action: move_to(other_position) });
+        for key in &other_metadata.held_keys {
+            actions.push(MacroAction {
+                offset,
+                action: UserAction::Key(KeyAction { key: key.clone(), pressed: true })
+            });
+        }
+
+        for action in other_actions {
+            let moved = match &action.action {
+                UserAction::MouseMove(mouse) => {
+                    other_position = match other_metadata.mouse_mode {
+                        MouseMode::Absolute => (mouse.x, mouse.y),
+                        MouseMode::Relative => (other_position.0 + mouse.x, other_position.1 + mouse.y)
+                    };
+                    move_to(other_position)
+                }
+                _ => action.action
+            };
+
+            actions.push(MacroAction { offset: action.offset + offset, action: moved });
+        }
+
+        // Keep the joined actions in time order.
+        actions.sort_by_key(|a| a.offset);
+
+        metadata.end += other_metadata.end;
+    }
+
+    /// Merges runs of mouse moves which happen close together,
+    /// and removes mouse moves which don't move the cursor.
+    ///
+    /// Moves are only merged with moves which directly follow them and
+    /// happen within a few milliseconds, so the order and timing of
+    /// other actions is unchanged. The merged move happens when the
+    /// last move of its run did.
+    ///
+    /// Returns the number of actions which were removed.
+    pub fn optimize(&mut self) -> usize {
+        let mut actions = utils::lock(&self.actions);
+        let metadata = utils::lock(&self.metadata);
+        let window = COALESCE_WINDOW.as_micros() as u64;
+        let count = actions.len();
+
+        let mut optimized: Vec<MacroAction> = Vec::with_capacity(count);
+        let mut position = metadata.cursor_pos;
+        // The offset of the first move in the run being merged, if any.
+        let mut run_start = None;
+        // Relative moves are summed through an accumulator, so a merged
+        // run moves exactly as far as the moves it replaces.
+        let mut sub_pixel = utils::SubPixel::default();
+
+        for action in actions.drain(..) {
+            let UserAction::MouseMove(mouse) = &action.action else {
+                run_start = None;
+                optimized.push(action);
+                continue;
+            };
+
+            // Drop moves which go nowhere.
+            let moved = match metadata.mouse_mode {
+                MouseMode::Absolute => (mouse.x, mouse.y) != position,
+                MouseMode::Relative => (mouse.x, mouse.y) != (0, 0)
+            };
+            if !moved {
+                continue;
+            }
+            position = (mouse.x, mouse.y);
+
+            // Merge the move into the previous one if it's part of the same run.
+            if let Some(start) = run_start
+                && action.offset.saturating_sub(start) <= window
+                && let Some(MacroAction { offset, action: UserAction::MouseMove(last) }) = optimized.last_mut() {
+                match metadata.mouse_mode {
+                    MouseMode::Absolute => (last.x, last.y) = (mouse.x, mouse.y),
+                    MouseMode::Relative => {
+                        let (dx, dy) = sub_pixel.step(mouse.x as f64, mouse.y as f64);
+                        (last.x, last.y) = (last.x + dx, last.y + dy)
+                    }
+                }
+                *offset = action.offset;
+                continue;
+            }
+
+            run_start = Some(action.offset);
+            optimized.push(action);
+        }
+
+        // Relative moves which cancel each other out go nowhere either.
+        if metadata.mouse_mode == MouseMode::Relative {
+            optimized.retain(|a| !matches!(&a.action, UserAction::MouseMove(mouse) if (mouse.x, mouse.y) == (0, 0)));
+        }
+
+        *actions = optimized;
+        count - actions.len()
+    }
+
+    /// Removes the idle time before the first action and after the last one.
+    ///
+    /// The first action is moved to the start of the macro,
+    /// and the macro ends with its last action.
+    pub fn trim(&mut self) {
+        let (first, last) = {
+            let actions = utils::lock(&self.actions);
+            let first = actions.iter().map(|a| a.offset).min().unwrap_or(0);
+            let last = actions.iter().map(|a| a.offset).max().unwrap_or(0);
+            (first, last)
+        };
+
+        self.trim_to(Duration::from_micros(first), Duration::from_micros(last));
+    }
+
+    /// Crops this macro to the actions between `start` and `end`, inclusive.
+    ///
+    /// Actions outside the window are removed, and the rest are moved
+    /// so that `start` becomes the start of the macro. The macro starts
+    /// from where the cursor was at `start`.
+    pub fn trim_to(&mut self, start: Duration, end: Duration) {
+        let start = start.as_micros() as u64;
+        let end = end.as_micros() as u64;
+
+        let mut actions = utils::lock(&self.actions);
+        let mut metadata = utils::lock(&self.metadata);
+
+        let (before, kept): (Vec<_>, Vec<_>) = actions.drain(..)
+            .filter(|a| a.offset <= end)
+            .partition(|a| a.offset < start);
+
+        *actions = kept.into_iter()
+            .map(|mut a| {
+                a.offset -= start;
+                a
+            })
+            .collect();
+        metadata.cursor_pos = metadata.cursor_after(&before);
+        metadata.held_keys = metadata.keys_after(&before);
+        metadata.end = end.min(metadata.end).saturating_sub(start);
+    }
+
+    /// Splits this macro into two at the given offset.
+    ///
+    /// The first macro contains every action before `at` and ends
+    /// at `at`. The second macro contains the remaining actions,
+    /// including any exactly at `at`, re-based so that `at` becomes
+    /// its start.
+    ///
+    /// The first macro keeps the original starting cursor position.
+    /// The second macro starts from where the cursor was at the cut.
+    pub fn split_at(&self, at: Duration) -> (Macro, Macro) {
+        let offset = at.as_micros() as u64;
+        let actions = utils::lock(&self.actions);
+        let metadata = utils::lock(&self.metadata);
+
+        let (before, after): (Vec<_>, Vec<_>) = actions.iter()
+            .cloned()
+            .partition(|a| a.offset < offset);
+
+        // Find where the cursor was when the cut happened.
+        let cursor_pos = metadata.cursor_after(&before);
+
+        let after = after.into_iter()
+            .map(|mut a| {
+                a.offset -= offset;
+                a
+            })
+            .collect();
+
+        let mut first = metadata.clone();
+        first.end = offset.min(metadata.end);
+
+        let mut second = metadata.clone();
+        second.end = metadata.end.saturating_sub(offset);
+        second.cursor_pos = cursor_pos;
+        second.held_keys = metadata.keys_after(&before);
+
+        (Macro::from_parts(before, first), Macro::from_parts(after, second))
+    }
+
+    /// Saves this macro to the file system.
+    #[cfg(feature = "save")]
+    pub fn save<S: AsRef<str>>(&self, path: S) {
+        let content = serde_json::to_string(self)
+            .expect("Failed to serialize macro");
+        write_file(path.as_ref(), content);
+    }
+
+    /// Saves this macro to the file system as indented, multi-line JSON.
+    ///
+    /// Pretty macros are larger than ones saved with `save`, but are
+    /// much easier to read and to compare in version control.
+    #[cfg(feature = "save")]
+    pub fn save_pretty<S: AsRef<str>>(&self, path: S) {
+        let content = serde_json::to_string_pretty(self)
+            .expect("Failed to serialize macro");
+        write_file(path.as_ref(), content);
+    }
+
+    /// Loads a macro previously saved with `save` from the file system.
+    ///
+    /// With the `compress` feature, macros saved with `save_compressed`
+    /// can be loaded too; they're told apart by the gzip magic bytes.
+    #[cfg(feature = "save")]
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Macro, LoadError> {
+        let content = std::fs::read(path)?;
+
+        #[cfg(feature = "compress")]
+        if content.starts_with(&GZIP_MAGIC) {
+            return Macro::decompress(&content);
+        }
+
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Saves this macro to the file system as gzip-compressed JSON.
+    ///
+    /// Recordings with many mouse moves compress very well, since
+    /// their actions repeat the same structure over and over.
+    #[cfg(feature = "compress")]
+    pub fn save_compressed<S: AsRef<str>>(&self, path: S) {
+        use std::io::Write;
+
+        let content = serde_json::to_vec(self)
+            .expect("Failed to serialize macro");
+
+        // Compressing into memory only fails if allocation does.
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&content).expect("Failed to compress macro");
+        write_file(path.as_ref(), encoder.finish().expect("Failed to compress macro"));
+    }
+
+    /// Loads a macro previously saved with `save_compressed` from the file system.
+    #[cfg(feature = "compress")]
+    pub fn load_compressed<P: AsRef<std::path::Path>>(path: P) -> Result<Macro, LoadError> {
+        let content = std::fs::read(path)?;
+        Macro::decompress(&content)
+    }
+
+    /// Decompresses and deserializes a macro saved with `save_compressed`.
+    #[cfg(feature = "compress")]
+    fn decompress(content: &[u8]) -> Result<Macro, LoadError> {
+        use std::io::Read;
+
+        let mut json = Vec::new();
+        flate2::read::GzDecoder::new(content).read_to_end(&mut json)?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    /// Saves this macro to the file system in a compact binary format.
+    ///
+    /// Binary macros are much smaller than JSON ones and quicker to
+    /// load, but can't be read or edited by hand.
+    #[cfg(feature = "binary")]
+    pub fn save_binary<S: AsRef<str>>(&self, path: S) {
+        let content = bincode::serialize(self)
+            .expect("Failed to serialize macro");
+        write_file(path.as_ref(), content);
+    }
+
+    /// Loads a macro previously saved with `save_binary` from the file system.
+    #[cfg(feature = "binary")]
+    pub fn load_binary<P: AsRef<std::path::Path>>(path: P) -> Result<Macro, LoadError> {
+        let content = std::fs::read(path)?;
+        Ok(bincode::deserialize(&content)?)
+    }
+}
+
+/// Writes a saved macro to the file system, creating its directory if needed.
+#[cfg(any(feature = "save", feature = "binary"))]
+fn write_file<C: AsRef<[u8]>>(path: &str, content: C) {
+    if let Some(parent) = std::path::Path::new(path).parent()
+        && !parent.exists() {
+        std::fs::create_dir_all(parent).expect("Failed to create directory");
+    }
+
+    if let Err(e) = std::fs::write(path, content) {
+        log::error!("Failed to write macro to file: {}", e);
+    }
+}
+
+/// Creates a handle to the same macro.
+///
+/// The clone shares the actions, metadata, and recording state of
+/// this macro; recording into or editing one changes the other. Only
+/// the settings, the key remapping table, and the connection to the
+/// input system are separate, and the clone connects to the input
+/// system on its own the first time it's played back.
+///
+/// This is intentional, so that a macro can be recorded on another
+/// thread; use `Macro::deep_copy` for an independent copy.
+impl Clone for Macro {
+    fn clone(&self) -> Self {
+        Macro {
+            enigo: None,
+            settings: self.settings.clone(),
+            poll_interval: self.poll_interval,
+            remap: self.remap.clone(),
+            display_scale: self.display_scale,
+            metadata: self.metadata.clone(),
+            start_time: self.start_time.clone(),
+            is_recording: self.is_recording.clone(),
+            actions: self.actions.clone()
+        }
+    }
+}
+
+impl Serialize for Macro {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        let actions = utils::lock(&self.actions);
+        let metadata = utils::lock(&self.metadata);
+        let mut state = serializer.serialize_struct("Macro", 3)?;
+        state.serialize_field("version", &FORMAT_VERSION)?;
+        state.serialize_field("actions", &*actions)?;
+        state.serialize_field("metadata", &*metadata)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Macro {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_struct("Macro", FIELDS, MacroVisitor)
+    }
+}
+
+/// The fields of a serialized `Macro`.
+const FIELDS: &[&str] = &["version", "actions", "metadata"];
+
+struct MacroVisitor;
+
+impl<'de> Visitor<'de> for MacroVisitor {
+    type Value = Macro;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a macro with actions and metadata")
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut version = None;
+        let mut actions = None;
+        let mut metadata = None;
+
+        let mut map = map;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => {
+                    if version.is_some() {
+                        return Err(serde::de::Error::duplicate_field("version"));
+                    }
+                    version = Some(check_version(map.next_value::<u32>()?)?);
+                }
+                "actions" => {
+                    if actions.is_some() {
+                        return Err(serde::de::Error::duplicate_field("actions"));
+                    }
+                    actions = Some(map.next_value::<Vec<MacroAction>>()?);
+                }
+                "metadata" => {
+                    if metadata.is_some() {
+                        return Err(serde::de::Error::duplicate_field("metadata"));
+                    }
+                    metadata = Some(map.next_value::<MacroMetadata>()?);
+                }
+                _ => return Err(serde::de::Error::unknown_field(&key, FIELDS)),
+            }
+        }
+
+        let actions = actions
+            .ok_or_else(|| serde::de::Error::missing_field("actions"))?;
+        let metadata = metadata
+            .ok_or_else(|| serde::de::Error::missing_field("metadata"))?;
+
+        upgrade(version, actions, metadata)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        // Formats which aren't self-describing store the fields in order.
+        let version = seq.next_element::<u32>()?
+            .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+        let version = check_version(version)?;
+        let actions = seq.next_element::<Vec<MacroAction>>()?
+            .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let metadata = seq.next_element::<MacroMetadata>()?
+            .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+        upgrade(Some(version), actions, metadata)
+    }
+}
+
+/// Rejects macros saved by a newer version of this library,
+/// whose actions may not be understood.
+pub(crate) fn check_version<E: serde::de::Error>(version: u32) -> Result<u32, E> {
+    if version > FORMAT_VERSION {
+        return Err(E::custom(format!(
+            "macro format version {} is newer than the supported version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+
+    Ok(version)
+}
+
+/// Converts an offset stored in milliseconds, as version 1 of the
+/// format did, to microseconds.
+///
+/// Returns an error if the offset is too large to convert.
+pub(crate) fn millis_to_micros<E: serde::de::Error>(offset: u64) -> Result<u64, E> {
+    offset.checked_mul(1000)
+        .ok_or_else(|| E::custom("offset out of range"))
+}
+
+/// Creates a macro from deserialized parts, converting
+/// them from older versions of the format if needed
+/// and sorting the actions by offset.
+fn upgrade<E: serde::de::Error>(version: Option<u32>, mut actions: Vec<MacroAction>, mut metadata: MacroMetadata) -> Result<Macro, E> {
+    // Macros without a version stored offsets in milliseconds.
+    if version.unwrap_or(1) < 2 {
+        for action in actions.iter_mut() {
+            action.offset = millis_to_micros(action.offset)?;
+        }
+        metadata.end = millis_to_micros(metadata.end)?;
+    }
+
+    // Edited macros may be out of order; a stable sort keeps
+    // actions which share an offset in the order they were written.
+    actions.sort_by_key(|a| a.offset);
+
+    Ok(Macro::from_parts(actions, metadata))
+}
+
+trait TimeSince {
+    /// Returns the time in microseconds since the given start time.
+    fn time_since(&self, start: Instant) -> u64;
+}
+
+impl TimeSince for Instant {
+    fn time_since(&self, start: Instant) -> u64 {
+        self.duration_since(start).as_micros() as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ActionKind;
+
+    #[test]
+    fn create_macro() {
+        sleep(Duration::from_secs(1));
+
+        let mut towa = Macro::new();
+        let thread_towa = towa.clone();
+        std::thread::spawn(move || {
+            let _guard = thread_towa.record();
+            thread_towa.wait_until_stopped();
+        });
+
+        sleep(Duration::from_secs(3));
+        towa.stop_recording();
+
+        sleep(Duration::from_secs(2));
+        towa.playback_or_panic();
+    }
+
+    #[test]
+    fn record_duration() {
+        let towa = Macro::new();
+        // The macro's lifetime before recording shouldn't count.
+        sleep(Duration::from_millis(500));
+
+        let guard = towa.record();
+        sleep(Duration::from_millis(300));
+        towa.stop_recording();
+        drop(guard);
+
+        let end = towa.metadata.lock().unwrap().end;
+        assert!((300_000..400_000).contains(&end), "recorded end was {end}µs");
+    }
+
+    #[test]
+    fn record_for_zero_duration() {
+        let towa = MacroBuilder::new()
+            .mouse_mode(MouseMode::Relative)
+            .push_key(Duration::from_millis(10), Keycode::A, true)
+            .build();
+
+        let guard = towa.record_for(Duration::ZERO);
+        assert!(!towa.is_recording());
+        drop(guard);
+
+        assert!(towa.actions.lock().unwrap().is_empty());
+        assert_eq!(*towa.metadata.lock().unwrap(), MacroMetadata { mouse_mode: MouseMode::Relative, ..Default::default() });
+    }
+
+    #[test]
+    fn drop_guard_sets_end() {
+        let towa = Macro::from_parts(vec![], MacroMetadata::default());
+        let start = Instant::now() - Duration::from_millis(200);
+        *towa.start_time.lock().unwrap() = start;
+        *towa.is_recording.lock() = true;
+
+        let recording = Recording {
+            start,
+            is_recording: Arc::downgrade(&towa.is_recording),
+            start_time: Arc::downgrade(&towa.start_time),
+            metadata: Arc::downgrade(&towa.metadata)
+        };
+        drop(MacroGuard::new().stops(recording));
+
+        assert!(!towa.is_recording());
+        let end = towa.metadata.lock().unwrap().end;
+        assert!((200_000..300_000).contains(&end), "recorded end was {end}µs");
+
+        // Stopping again doesn't move the end.
+        sleep(Duration::from_millis(10));
+        towa.stop_recording();
+        assert_eq!(towa.metadata.lock().unwrap().end, end);
+    }
+
+    #[test]
+    fn stop_after_limit() {
+        let towa = Macro::from_parts(vec![], MacroMetadata::default());
+        let start = Instant::now();
+        *towa.start_time.lock().unwrap() = start;
+        *towa.is_recording.lock() = true;
+
+        let recording = Recording {
+            start,
+            is_recording: Arc::downgrade(&towa.is_recording),
+            start_time: Arc::downgrade(&towa.start_time),
+            metadata: Arc::downgrade(&towa.metadata)
+        };
+        let timer = recording.clone().stop_after(Duration::from_millis(50)).unwrap();
+        towa.wait_until_stopped();
+        timer.join().unwrap();
+
+        let end = towa.metadata.lock().unwrap().end;
+        assert!((50_000..150_000).contains(&end), "recorded end was {end}µs");
+
+        // Stopping the recording sooner cancels the timer.
+        *towa.is_recording.lock() = true;
+        let timer = recording.stop_after(Duration::from_secs(10)).unwrap();
+        towa.stop_recording();
+        timer.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn recover_from_poisoned_lock() {
+        let towa = Macro::from_parts(vec![], MacroMetadata { end: 10_000, cursor_pos: (0, 0), ..Default::default() });
+        *towa.is_recording.lock() = true;
+        let recorder = Recorder {
+            start: Instant::now(),
+            actions: towa.actions.clone(),
+            is_recording: towa.is_recording.clone(),
+            start_time: towa.start_time.clone(),
+            metadata: towa.metadata.clone()
+        };
+
+        // Panic in a callback while it holds the macro's state.
+        let callback = recorder.clone();
+        let result = std::thread::spawn(move || {
+            let _actions = callback.actions.lock().unwrap();
+            let _metadata = callback.metadata.lock().unwrap();
+            panic!("callback panicked");
+        }).join();
+        assert!(result.is_err());
+        assert!(towa.actions.is_poisoned());
+
+        recorder.push(UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }));
+        towa.stop_recording();
+        assert_eq!(towa.actions().len(), 1);
+        assert!(towa.duration() < Duration::from_secs(1));
+        assert!(!towa.to_script().is_empty());
+    }
+
+    #[test]
+    fn ignore_actions_after_stop() {
+        let towa = Macro::from_parts(vec![], MacroMetadata::default());
+        *towa.is_recording.lock() = true;
+        let recorder = Recorder {
+            start: Instant::now(),
+            actions: towa.actions.clone(),
+            is_recording: towa.is_recording.clone(),
+            start_time: towa.start_time.clone(),
+            metadata: towa.metadata.clone()
+        };
+
+        let key = |pressed| UserAction::Key(KeyAction { key: "A".to_string(), pressed });
+        recorder.push(key(true));
+        recorder.stop();
+        recorder.push(key(false));
+
+        assert!(!towa.is_recording());
+        assert_eq!(towa.actions().len(), 1);
+    }
+
+    #[test]
+    fn wait_until_stopped_wakes() {
+        let towa = Macro::from_parts(vec![], MacroMetadata::default());
+        *towa.is_recording.lock() = true;
+
+        let thread_towa = towa.clone();
+        let thread = std::thread::spawn(move || {
+            sleep(Duration::from_millis(50));
+            thread_towa.stop_recording();
+        });
+
+        towa.wait_until_stopped();
+        assert!(!towa.is_recording());
+        thread.join().unwrap();
+
+        // Waiting on a stopped macro returns immediately.
+        towa.wait_until_stopped();
+    }
+
+    #[test]
+    fn record_append_continues() {
+        let actions = vec![MacroAction {
+            offset: 50_000,
+            action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true })
+        }];
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 100_000, cursor_pos: (12, 34), ..Default::default() });
+
+        let guard = towa.record_append();
+        sleep(Duration::from_millis(200));
+        towa.stop_recording();
+        drop(guard);
+
+        let metadata = towa.metadata.lock().unwrap();
+        assert!((300_000..400_000).contains(&metadata.end), "recorded end was {}µs", metadata.end);
+        assert_eq!(metadata.cursor_pos, (12, 34));
+        assert_eq!(towa.actions.lock().unwrap()[0].offset, 50_000);
+    }
+
+    #[test]
+    fn mouse_only_recording_clears_held_keys() {
+        let metadata = MacroMetadata { end: 100_000, cursor_pos: (0, 0), held_keys: vec!["LShift".to_string()], ..Default::default() };
+        let towa = Macro::from_parts(Vec::new(), metadata);
+
+        // Keys held during an earlier recording aren't pressed again.
+        let guard = towa.record_filtered(RecordFilter::MOUSE_BUTTONS | RecordFilter::MOUSE_MOVE);
+        towa.stop_recording();
+        drop(guard);
+
+        assert!(towa.metadata.lock().unwrap().held_keys.is_empty());
+    }
+
+    #[test]
+    fn combine_record_filters() {
+        let filter = RecordFilter::KEYS | RecordFilter::MOUSE_BUTTONS;
+        assert!(filter.contains(RecordFilter::KEYS));
+        assert!(filter.contains(RecordFilter::MOUSE_BUTTONS));
+        assert!(!filter.contains(RecordFilter::MOUSE_MOVE));
+        assert!(RecordFilter::default().contains(filter | RecordFilter::MOUSE_MOVE));
+    }
+
+    #[test]
+    fn split_macro() {
+        let actions = vec![
+            MacroAction { offset: 100_000, action: UserAction::MouseMove(MouseMoveAction { x: 10, y: 20 }) },
+            MacroAction { offset: 200_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            MacroAction { offset: 300_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: false }) },
+            MacroAction { offset: 400_000, action: UserAction::MouseMove(MouseMoveAction { x: 30, y: 40 }) },
+        ];
+        let metadata = MacroMetadata { end: 500_000, cursor_pos: (0, 0), ..Default::default() };
+        let towa = Macro::from_parts(actions, metadata);
+
+        let (first, second) = towa.split_at(Duration::from_millis(300));
+        let first_actions = first.actions.lock().unwrap();
+        let second_actions = second.actions.lock().unwrap();
+
+        assert_eq!(first_actions.len() + second_actions.len(), 4);
+        assert_eq!(first_actions.len(), 2);
+        assert_eq!(second_actions.iter().map(|a| a.offset).collect::<Vec<_>>(), vec![0, 100_000]);
+
+        let first_metadata = first.metadata.lock().unwrap();
+        let second_metadata = second.metadata.lock().unwrap();
+        assert_eq!(first_metadata.end + second_metadata.end, 500_000);
+        assert_eq!(first_metadata.cursor_pos, (0, 0));
+        assert_eq!(second_metadata.cursor_pos, (10, 20));
+    }
 
-        // Clear existing actions.
-        self.actions.lock().unwrap().clear();
+    #[test]
+    fn split_while_key_held() {
+        let key = |offset, key: &str, pressed| MacroAction {
+            offset,
+            action: UserAction::Key(KeyAction { key: key.to_string(), pressed })
+        };
+        let actions = vec![key(100_000, "A", true), key(200_000, "LShift", false), key(400_000, "A", false)];
+        let metadata = MacroMetadata { end: 500_000, held_keys: vec!["LShift".to_string()], ..Default::default() };
+        let towa = Macro::from_parts(actions, metadata);
+
+        let (first, second) = towa.split_at(Duration::from_millis(300));
+        assert_eq!(first.metadata.lock().unwrap().held_keys, vec!["LShift".to_string()]);
+        assert_eq!(second.metadata.lock().unwrap().held_keys, vec!["A".to_string()]);
+    }
 
-        let start = Instant::now();
-        let listener = DeviceEventsHandler::new(Duration::from_micros(100)).unwrap();
+    #[test]
+    fn split_relative_macro() {
+        let actions = vec![
+            MacroAction { offset: 100_000, action: UserAction::MouseMove(MouseMoveAction { x: 10, y: 20 }) },
+            MacroAction { offset: 200_000, action: UserAction::MouseMove(MouseMoveAction { x: 5, y: -5 }) },
+            MacroAction { offset: 400_000, action: UserAction::MouseMove(MouseMoveAction { x: 30, y: 40 }) },
+        ];
+        let metadata = MacroMetadata { end: 500_000, cursor_pos: (100, 100), mouse_mode: MouseMode::Relative, ..Default::default() };
+        let towa = Macro::from_parts(actions, metadata);
+
+        let (_, second) = towa.split_at(Duration::from_millis(300));
+        let second_metadata = second.metadata.lock().unwrap();
+        assert_eq!(second_metadata.cursor_pos, (115, 115));
+        assert_eq!(second_metadata.mouse_mode, MouseMode::Relative);
+    }
 
-        // Set the starting cursor position.
-        let state = DeviceState::new();
-        let (x, y) = state.get_mouse().coords;
-        // Store the initial cursor position in the metadata.
-        self.metadata.lock().unwrap().cursor_pos = (x, y);
-
-        let key_up = self.actions.clone();
-        let key_down = self.actions.clone();
-        let mouse_up = self.actions.clone();
-        let mouse_down = self.actions.clone();
-        let mouse_move = self.actions.clone();
-
-        // Start listening for device events.
-        let key_up_guard = listener.on_key_up(move |key| {
-            // Record the key up action.
-            key_up.lock().unwrap().push(MacroAction {
-                offset: Instant::now().time_since(start),
-                action: UserAction::Key(KeyAction { key: utils::to_string(key), pressed: false })
+    #[test]
+    fn append_relative_to_absolute() {
+        let first = vec![
+            MacroAction { offset: 100_000, action: UserAction::MouseMove(MouseMoveAction { x: 10, y: 20 }) },
+        ];
+        let second = vec![
+            MacroAction { offset: 50_000, action: UserAction::MouseMove(MouseMoveAction { x: 5, y: -5 }) },
+            MacroAction { offset: 60_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+        ];
+        let mut towa = Macro::from_parts(first, MacroMetadata { end: 200_000, cursor_pos: (0, 0), ..Default::default() });
+        let other = Macro::from_parts(second, MacroMetadata { end: 100_000, cursor_pos: (100, 100), mouse_mode: MouseMode::Relative, ..Default::default() });
+
+        towa.append_macro(&other);
+
+        let actions = towa.actions.lock().unwrap();
+        let moves = actions.iter()
+            .filter_map(|a| match &a.action {
+                UserAction::MouseMove(mouse) => Some((a.offset, mouse.x, mouse.y)),
+                _ => None
             })
-        });
+            .collect::<Vec<_>>();
+        assert_eq!(moves, vec![(100_000, 10, 20), (200_000, 100, 100), (250_000, 105, 95)]);
+        assert_eq!(actions.last().unwrap().offset, 260_000);
+        assert_eq!(towa.metadata.lock().unwrap().end, 300_000);
+    }
 
-        let key_down_guard = listener.on_key_down(move |key| {
-            // Record the key down action.
-            key_down.lock().unwrap().push(MacroAction {
-                offset: Instant::now().time_since(start),
-                action: UserAction::Key(KeyAction { key: utils::to_string(key), pressed: true })
+    #[test]
+    fn optimize_mouse_moves() {
+        let moved = |offset, x, y| MacroAction { offset, action: UserAction::MouseMove(MouseMoveAction { x, y }) };
+        let actions = vec![
+            moved(1_000, 1, 0),
+            moved(2_000, 0, 0),
+            moved(3_000, 2, 1),
+            MacroAction { offset: 4_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            moved(5_000, 3, 3),
+            moved(6_000, -3, -3),
+            moved(50_000, 1, 1),
+        ];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 100_000, cursor_pos: (0, 0), mouse_mode: MouseMode::Relative, ..Default::default() });
+
+        assert_eq!(towa.optimize(), 4);
+
+        let actions = towa.actions.lock().unwrap();
+        let summary = actions.iter()
+            .map(|a| match &a.action {
+                UserAction::MouseMove(mouse) => (a.offset, mouse.x, mouse.y),
+                _ => (a.offset, 0, 0)
             })
-        });
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![(3_000, 3, 1), (4_000, 0, 0), (50_000, 1, 1)]);
+    }
 
-        let mouse_up_guard = listener.on_mouse_up(move |button| {
-            // Record the mouse button up action.
-            mouse_up.lock().unwrap().push(MacroAction {
-                offset: Instant::now().time_since(start),
-                action: UserAction::MouseButton(MouseButtonAction { button: *button, pressed: false })
-            })
-        });
+    #[test]
+    fn trim_idle_time() {
+        let actions = vec![
+            MacroAction { offset: 1_000_000, action: UserAction::MouseMove(MouseMoveAction { x: 10, y: 20 }) },
+            MacroAction { offset: 1_200_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            MacroAction { offset: 1_500_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: false }) },
+            MacroAction { offset: 1_700_000, action: UserAction::MouseMove(MouseMoveAction { x: 30, y: 40 }) },
+        ];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 3_000_000, cursor_pos: (0, 0), ..Default::default() });
+
+        towa.trim();
+        let offsets = towa.actions.lock().unwrap().iter().map(|a| a.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 200_000, 500_000, 700_000]);
+        assert_eq!(towa.metadata.lock().unwrap().end, 700_000);
+
+        towa.trim_to(Duration::from_millis(100), Duration::from_millis(600));
+        let offsets = towa.actions.lock().unwrap().iter().map(|a| a.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![100_000, 400_000]);
+        assert!(offsets.windows(2).all(|w| w[0] <= w[1]));
+
+        let metadata = towa.metadata.lock().unwrap();
+        assert_eq!(metadata.end, 500_000);
+        assert_eq!(metadata.cursor_pos, (10, 20));
+    }
 
-        let mouse_down_guard = listener.on_mouse_down(move |button| {
-            // Record the mouse button down action.
-            mouse_down.lock().unwrap().push(MacroAction {
-                offset: Instant::now().time_since(start),
-                action: UserAction::MouseButton(MouseButtonAction { button: *button, pressed: true })
-            })
-        });
+    #[test]
+    fn seek_relative_macro() {
+        let moved = |offset, x, y| MacroAction { offset, action: UserAction::MouseMove(MouseMoveAction { x, y }) };
+        let actions = vec![moved(1_000_000, 10, 0), moved(2_000_000, 0, 10), moved(6_000_000, -5, -5)];
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 8_000_000, cursor_pos: (50, 50), mouse_mode: MouseMode::Relative, ..Default::default() });
+
+        let rest = towa.seek(Duration::from_secs(5));
+        let metadata = rest.metadata.lock().unwrap();
+        assert_eq!(metadata.cursor_pos, (60, 60));
+        assert_eq!(metadata.end, 3_000_000);
+        assert_eq!(rest.actions().len(), 1);
+        assert_eq!(rest.actions()[0].offset, Duration::from_secs(1));
+    }
 
-        let mouse_move_guard = listener.on_mouse_move(move |position| {
-            // Record the mouse move action.
-            let (x, y) = *position;
-            mouse_move.lock().unwrap().push(MacroAction {
-                offset: Instant::now().time_since(start),
-                action: UserAction::MouseMove(MouseMoveAction { x, y })
-            });
-        });
+    #[test]
+    fn skip_unmapped_keys() {
+        let actions = [
+            UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }),
+            UserAction::Key(KeyAction { key: "NotAKey".to_string(), pressed: true }),
+            UserAction::Key(KeyAction { key: "A".to_string(), pressed: false }),
+        ];
+
+        let mut enigo = Enigo::new(&Settings::default()).unwrap();
+        let options = PlaybackOptions::default();
+        let mut held = Held::default();
+        let mut outcome = PlaybackOutcome::default();
+        for action in actions.iter() {
+            action.play(&mut enigo, MouseMode::Absolute, &RemapTable::new(), &options, &mut held, &mut outcome).expect("failed to play action");
+        }
 
-        MacroGuard::new()
-            .keep_alive(key_up_guard)
-            .keep_alive(key_down_guard)
-            .keep_alive(mouse_up_guard)
-            .keep_alive(mouse_down_guard)
-            .keep_alive(mouse_move_guard)
+        assert_eq!(outcome.skipped_keys, 1);
+        assert_eq!(outcome.unknown_buttons, 0);
+        assert_eq!(outcome.dispatched + outcome.skipped_keys, actions.len());
     }
 
-    /// Stops the macro recording.
-    pub fn stop_recording(&self) {
-        // This will stop any threads from holding on
-        // to the recording state if they use `is_recording()`.
-        *self.is_recording.lock().unwrap() = false;
+    #[test]
+    fn shift_moves_to_launch_position() {
+        let moves = [
+            MouseMoveAction { x: 100, y: 100 },
+            MouseMoveAction { x: 120, y: 90 },
+            MouseMoveAction { x: 0, y: 300 },
+        ];
+
+        let options = PlaybackOptions::anchored((100, 100), (150, 80));
+        let positions = moves.iter()
+            .map(|m| m.position(&options))
+            .collect::<Vec<_>>();
+
+        assert_eq!(positions, vec![(150, 80), (170, 70), (50, 280)]);
+    }
 
-        // Set the end time of the macro.
-        let start_time = *self.start_time.lock().unwrap();
-        self.metadata.lock().unwrap().end = Instant::now().time_since(start_time);
+    #[test]
+    fn scale_absolute_positions() {
+        let options = PlaybackOptions::new().scale(0.75, 0.75);
+        assert_eq!(MouseMoveAction { x: 2560, y: 1440 }.position(&options), (1920, 1080));
+        assert_eq!(MouseMoveAction { x: 101, y: 0 }.position(&options), (76, 0));
     }
 
-    /// Checks if a macro is currently being recorded.
-    pub fn is_recording(&self) -> bool {
-        *self.is_recording.lock().unwrap()
+    #[test]
+    fn playback_each_action_once() {
+        let key = |offset, pressed| MacroAction {
+            offset,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed })
+        };
+        let actions = vec![key(5_000, true), key(5_000, false), key(5_400, true), key(8_000, false)];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 20_000, cursor_pos: (0, 0), ..Default::default() });
+
+        let outcome = towa.playback().expect("playback failed");
+        assert_eq!(outcome.dispatched, 4);
     }
 
-    /// Plays any stored macro actions.
-    ///
-    /// This method will block until all actions have been played back.
-    pub fn playback(&mut self) {
-        let start = Instant::now();
-        let metadata = self.metadata.lock().unwrap();
-        let actions = self.actions.lock().unwrap();
+    #[test]
+    fn report_progress() {
+        let mut towa = Macro::from_parts(vec![], MacroMetadata { end: 50_000, cursor_pos: (0, 0), ..Default::default() });
+        assert_eq!(towa.duration(), Duration::from_millis(50));
 
-        // Move the cursor to the initial position.
-        let (x, y) = metadata.cursor_pos;
-        self.enigo.move_mouse(x, y, Coordinate::Abs).unwrap();
+        let mut reports = vec![];
+        towa.playback_with_progress(|fraction| reports.push(fraction)).expect("playback failed");
 
-        loop {
-            let offset = Instant::now().time_since(start);
+        assert!(reports.len() > 1, "only {} progress reports", reports.len());
+        assert!(reports.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(reports.last(), Some(&1.0));
+    }
 
-            // Check if the macro is over.
-            if offset >= metadata.end {
-                // Stop playback if the end time has been reached.
-                break;
-            }
+    #[test]
+    fn playback_events() {
+        let actions = vec![MacroAction {
+            offset: 5_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 8_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: false })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 20_000, cursor_pos: (0, 0), ..Default::default() });
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let outcome = towa.playback_with_events(tx).expect("playback failed");
+        let events = rx.iter().collect::<Vec<_>>();
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], PlaybackEvent::Started);
+        assert!(matches!(&events[1], PlaybackEvent::Action { offset, .. } if *offset >= Duration::from_millis(5)));
+        assert_eq!(events[3], PlaybackEvent::Finished(outcome));
+
+        // Playback carries on when nobody is listening.
+        let (tx, rx) = std::sync::mpsc::channel();
+        drop(rx);
+        assert_eq!(towa.playback_with_events(tx).expect("playback failed").dispatched, 2);
+    }
 
-            // Get the actions to play back.
-            for action in actions.iter()
-                .filter(|a| a.offset.eq(&offset)) {
-                match &action.action {
-                    UserAction::MouseMove(mouse) => {
-                        self.enigo.move_mouse(mouse.x, mouse.y, Coordinate::Abs).unwrap();
-                    }
-                    UserAction::MouseButton(mouse) => {
-                        let direction = if mouse.pressed {
-                            Direction::Press
-                        } else {
-                            Direction::Release
-                        };
-                        let button = match mouse.button {
-                            1 => Button::Left,
-                            2 => Button::Right,
-                            3 => Button::Middle,
-                            4 => Button::Back,
-                            5 => Button::Forward,
-                            _ => {
-                                eprintln!("Unknown mouse button: {}", mouse.button);
-                                continue;
-                            }
-                        };
-
-                        self.enigo.button(button, direction).unwrap();
-                    }
-                    UserAction::Key(key) => {
-                        let direction = if key.pressed {
-                            Direction::Press
-                        } else {
-                            Direction::Release
-                        };
-
-                        if let Some(key) = utils::remap(&key.key) {
-                            self.enigo.key(key, direction).unwrap();
-                        }
-                    }
-                }
-            }
+    #[test]
+    fn smooth_relative_moves() {
+        let actions = vec![
+            MacroAction { offset: 10_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            MacroAction { offset: 40_000, action: UserAction::MouseMove(MouseMoveAction { x: 10, y: -7 }) },
+        ];
+        let metadata = MacroMetadata { end: 50_000, cursor_pos: (0, 0), mouse_mode: MouseMode::Relative, ..Default::default() };
+
+        let smoothed = PlaybackOptions::new().smooth(3).smoothed(&actions, &metadata);
+        let moves = smoothed.iter()
+            .filter_map(|a| match &a.action {
+                UserAction::MouseMove(mouse) => Some((a.offset, mouse.x, mouse.y)),
+                _ => None
+            })
+            .collect::<Vec<_>>();
 
-            // Wait for the next millisecond.
-            sleep(Duration::from_micros(100));
-        }
+        // The moves start after the key press, and add up to the recorded move.
+        assert_eq!(moves.iter().map(|m| m.0).collect::<Vec<_>>(), vec![20_000, 30_000, 40_000]);
+        assert_eq!(moves.iter().map(|m| m.1).sum::<i32>(), 10);
+        assert_eq!(moves.iter().map(|m| m.2).sum::<i32>(), -7);
     }
 
-    /// Saves this macro to the file system.
-    #[cfg(feature = "save")]
-    pub fn save<S: AsRef<str>>(&self, path: S) {
-        if let Some(parent) = std::path::Path::new(path.as_ref()).parent() {
-            if !parent.exists() {
-                std::fs::create_dir_all(parent).expect("Failed to create directory");
-            }
-        }
+    #[test]
+    fn rescale_relative_moves() {
+        let moves = [(200, -3), (1, 1), (1, 1)];
+        let actions = moves.iter()
+            .map(|&(x, y)| MacroAction { offset: 0, action: UserAction::MouseMove(MouseMoveAction { x, y }) })
+            .collect::<Vec<_>>();
+        let metadata = MacroMetadata { mouse_mode: MouseMode::Relative, display_scale: Some(2.0), ..Default::default() };
+
+        let positions = rescaled(&actions, &metadata, Some(1.0)).iter()
+            .map(|a| match &a.action {
+                UserAction::MouseMove(mouse) => (mouse.x, mouse.y),
+                _ => unreachable!()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(positions.iter().fold((0, 0), |(x, y), (dx, dy)| (x + dx, y + dy)), (101, -1));
+        assert_eq!(positions[0].0, 100);
 
-        let content = serde_json::to_string(self)
-            .expect("Failed to serialize macro");
-        if let Err(e) = std::fs::write(path.as_ref(), content) {
-            eprintln!("Failed to write macro to file: {}", e);
-        }
+        // Nothing is scaled if either scale is unknown.
+        assert!(matches!(rescaled(&actions, &metadata, None), Cow::Borrowed(_)));
     }
-}
 
-impl Clone for Macro {
-    fn clone(&self) -> Self {
-        Macro {
-            enigo: Enigo::new(&Settings::default()).unwrap(),
-            metadata: self.metadata.clone(),
-            start_time: self.start_time.clone(),
-            is_recording: self.is_recording.clone(),
-            actions: self.actions.clone()
-        }
+    #[test]
+    fn rescale_many_small_moves() {
+        // A third of a pixel each, which rounds to nothing on its own.
+        let actions = (0..1000)
+            .map(|i| MacroAction { offset: i * 1_000, action: UserAction::MouseMove(MouseMoveAction { x: 1, y: -1 }) })
+            .collect::<Vec<_>>();
+        let metadata = MacroMetadata { mouse_mode: MouseMode::Relative, display_scale: Some(3.0), ..Default::default() };
+
+        let total = rescaled(&actions, &metadata, Some(1.0)).iter()
+            .fold((0, 0), |(x, y), a| match &a.action {
+                UserAction::MouseMove(mouse) => (x + mouse.x, y + mouse.y),
+                _ => (x, y)
+            });
+        assert_eq!(total, (333, -333));
     }
-}
 
-impl Serialize for Macro {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer
-    {
-        let actions = self.actions.lock().unwrap();
-        let metadata = self.metadata.lock().unwrap();
-        let mut state = serializer.serialize_struct("Macro", 2)?;
-        state.serialize_field("actions", &*actions)?;
-        state.serialize_field("metadata", &*metadata)?;
-        state.end()
+    #[test]
+    fn smooth_absolute_moves() {
+        let actions = vec![MacroAction { offset: 40_000, action: UserAction::MouseMove(MouseMoveAction { x: 100, y: 50 }) }];
+        let metadata = MacroMetadata { end: 50_000, cursor_pos: (0, 0), ..Default::default() };
+
+        let smoothed = PlaybackOptions::new().smooth(4).smoothed(&actions, &metadata);
+        let positions = smoothed.iter()
+            .map(|a| match &a.action {
+                UserAction::MouseMove(mouse) => (mouse.x, mouse.y),
+                _ => unreachable!()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(25, 13), (50, 25), (75, 38), (100, 50)]);
     }
-}
 
-impl<'de> Deserialize<'de> for Macro {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>
-    {
-        deserializer.deserialize_struct("Macro", &["actions", "metadata"], MacroVisitor)
+    #[test]
+    fn jitter_keeps_moves_and_order() {
+        let key = |offset, pressed| MacroAction { offset, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed }) };
+        let actions = vec![
+            key(0, true),
+            MacroAction { offset: 20_000, action: UserAction::MouseMove(MouseMoveAction { x: 7, y: -3 }) },
+            key(21_000, false),
+            MacroAction { offset: 40_000, action: UserAction::MouseMove(MouseMoveAction { x: -5, y: 12 }) }
+        ];
+        let metadata = MacroMetadata { end: 50_000, cursor_pos: (0, 0), mouse_mode: MouseMode::Relative, ..Default::default() };
+        let config = JitterConfig::new(42).timing(Duration::from_millis(5)).movement(4);
+        let options = PlaybackOptions::new().jitter(config);
+
+        let wandered = options.wandered(Cow::Borrowed(&actions), &metadata);
+        assert_eq!(wandered.len(), 6);
+
+        // The moves still add up to the recorded ones.
+        let total = wandered.iter()
+            .filter_map(|a| match &a.action {
+                UserAction::MouseMove(mouse) => Some((mouse.x, mouse.y)),
+                _ => None
+            })
+            .fold((0, 0), |(x, y), (dx, dy)| (x + dx, y + dy));
+        assert_eq!(total, (2, 9));
+
+        // Actions are never reordered, and the same seed gives the same run.
+        let offsets = options.offsets(&wandered, metadata.end);
+        assert!(offsets.windows(2).all(|w| w[0] <= w[1]));
+        let keys = wandered.iter()
+            .filter_map(|a| match &a.action {
+                UserAction::Key(key) => Some(key.pressed),
+                _ => None
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![true, false]);
+        assert_eq!(options.offsets(&options.wandered(Cow::Borrowed(&actions), &metadata), metadata.end), offsets);
     }
-}
 
-struct MacroVisitor;
+    #[test]
+    fn correct_relative_drift() {
+        let actions = vec![MacroAction {
+            offset: 10_000,
+            action: UserAction::MouseMove(MouseMoveAction { x: 30, y: -20 })
+        }];
+        let metadata = MacroMetadata { end: 20_000, cursor_pos: (200, 200), mouse_mode: MouseMode::Relative, ..Default::default() };
+        let mut towa = Macro::from_parts(actions, metadata);
+
+        let outcome = towa.playback_with(&PlaybackOptions::new().correct_drift(true)).expect("playback failed");
+        assert!(outcome.drift.is_some());
+        assert_eq!(DeviceState::new().get_mouse().coords, (230, 180));
+    }
 
-impl<'de> Visitor<'de> for MacroVisitor {
-    type Value = Macro;
+    #[test]
+    fn skip_window_check_without_title() {
+        let metadata = MacroMetadata::default();
+        let options = PlaybackOptions::new().check_window(WindowCheck::Require);
+        assert!(options.check_focused_window(&metadata).is_ok());
+    }
 
-    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-        formatter.write_str("a macro with actions and metadata")
+    #[test]
+    fn step_through_actions() {
+        let actions = vec![MacroAction {
+            offset: 5_000_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 9_000_000,
+            action: UserAction::MouseMove(MouseMoveAction { x: 10, y: 10 })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 10_000_000, cursor_pos: (0, 0), ..Default::default() });
+
+        // Steps don't wait for the actions' offsets.
+        let start = Instant::now();
+        let mut cursor = towa.step_playback().expect("failed to start playback");
+        let view = cursor.next().unwrap().expect("failed to play action");
+        assert_eq!(view.kind, ActionKind::Key { key: "LShift".to_string(), pressed: true, mapped: true });
+        assert_eq!(cursor.remaining(), 1);
+        assert!(cursor.next().unwrap().is_ok());
+        assert!(cursor.next().is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        assert_eq!(cursor.outcome().dispatched, 2);
     }
 
-    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
-    where
-        A: MapAccess<'de>,
-    {
-        let mut actions = None;
-        let mut metadata = None;
+    #[test]
+    fn play_remapped_key() {
+        let mut remap = RemapTable::new();
+        remap.insert("Escape", Key::Shift);
+        let mut towa = MacroBuilder::new()
+            .remap(remap)
+            .push_key(Duration::from_millis(10), Keycode::Escape, true)
+            .push_key(Duration::from_millis(20), Keycode::Escape, false)
+            .build();
+
+        // The overridden key is pressed instead of the built-in one.
+        let mut cursor = towa.step_playback().expect("failed to start playback");
+        assert!(cursor.next().unwrap().is_ok());
+        assert_eq!(cursor.held.keys, vec![Key::Shift]);
+        assert!(cursor.next().unwrap().is_ok());
+        assert!(cursor.held.keys.is_empty());
+    }
 
-        let mut map = map;
+    #[test]
+    fn playback_at_double_speed() {
+        let actions = vec![MacroAction {
+            offset: 100_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 150_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: false })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 400_000, cursor_pos: (0, 0), ..Default::default() });
 
-        while let Some(key) = map.next_key::<String>()? {
-            match key.as_str() {
-                "actions" => {
-                    actions = Some(map.next_value::<Vec<MacroAction>>()?);
-                }
-                "metadata" => {
-                    metadata = Some(map.next_value::<MacroMetadata>()?);
-                }
-                _ => return Err(serde::de::Error::unknown_field(&key, &["actions", "metadata"])),
-            }
-        }
+        let start = Instant::now();
+        let outcome = towa.playback_with_speed(2.0).expect("playback failed");
+        let elapsed = start.elapsed();
 
-        let actions = actions
-            .ok_or_else(|| serde::de::Error::missing_field("actions"))?;
-        let metadata = metadata
-            .ok_or_else(|| serde::de::Error::missing_field("metadata"))?;
+        assert_eq!(outcome.dispatched, 2);
+        assert!(elapsed >= Duration::from_millis(200));
+        assert!(elapsed < Duration::from_millis(300));
+    }
 
-        Ok(Macro {
-            enigo: Enigo::new(&Settings::default()).unwrap(),
-            start_time: Arc::new(Mutex::new(Instant::now())),
-            is_recording: Arc::new(Mutex::new(false)),
-            actions: Arc::new(Mutex::new(actions)),
-            metadata: Arc::new(Mutex::new(metadata)),
-        })
+    #[test]
+    #[should_panic]
+    fn reject_zero_interval() {
+        Macro::from_parts(vec![], MacroMetadata::default())
+            .record_with_interval(Duration::ZERO);
     }
-}
 
-trait TimeSince {
-    /// Returns the time in milliseconds since the given start time.
-    fn time_since(&self, start: Instant) -> u64;
-}
+    #[test]
+    #[should_panic]
+    fn reject_zero_speed() {
+        PlaybackOptions::new().speed(0.0);
+    }
 
-impl TimeSince for Instant {
-    fn time_since(&self, start: Instant) -> u64 {
-        self.duration_since(start).as_millis() as u64
+    #[test]
+    fn playback_repeated() {
+        let actions = vec![MacroAction {
+            offset: 5_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 10_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: false })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 20_000, cursor_pos: (0, 0), ..Default::default() });
+
+        assert_eq!(towa.playback_repeat(0).expect("playback failed"), PlaybackOutcome::default());
+        assert_eq!(towa.playback_repeat(3).expect("playback failed").dispatched, 6);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn stop_looped_playback() {
+        let actions = vec![MacroAction {
+            offset: 5_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 10_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: false })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 20_000, cursor_pos: (0, 0), ..Default::default() });
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopper = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                sleep(Duration::from_millis(100));
+                stop.store(true, Ordering::Relaxed);
+            })
+        };
+
+        let outcome = towa.playback_loop(stop).expect("playback failed");
+        stopper.join().expect("stopping thread panicked");
+        assert!(outcome.dispatched >= 4);
+    }
 
     #[test]
-    fn create_macro() {
-        sleep(Duration::from_secs(1));
+    fn stop_async_playback() {
+        let actions = vec![MacroAction {
+            offset: 10_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 5_000_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: false })
+        }];
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 10_000_000, cursor_pos: (0, 0), ..Default::default() });
 
-        let mut towa = Macro::new();
-        let thread_towa = towa.clone();
-        std::thread::spawn(move || {
-            let _guard = thread_towa.record();
-            while thread_towa.is_recording() {
-                // Busy wait for the recording to finish.
-            }
-        });
+        let start = Instant::now();
+        let handle = towa.playback_async();
+        sleep(Duration::from_millis(100));
+        handle.stop();
 
-        sleep(Duration::from_secs(3));
-        towa.stop_recording();
+        let outcome = handle.join().expect("playback thread panicked").expect("playback failed");
+        assert_eq!(outcome.dispatched, 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
 
-        sleep(Duration::from_secs(2));
-        towa.playback();
+    #[test]
+    fn inspect_during_async_playback() {
+        let actions = vec![MacroAction {
+            offset: 10_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 5_000_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: false })
+        }];
+        let towa = Arc::new(Macro::from_parts(actions, MacroMetadata { end: 10_000_000, cursor_pos: (0, 0), ..Default::default() }));
+
+        let handle = towa.playback_async();
+        sleep(Duration::from_millis(100));
+
+        // The actions can be read while the macro is still playing.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let reader = {
+            let towa = towa.clone();
+            std::thread::spawn(move || tx.send(towa.actions().len()).ok())
+        };
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok(2));
+        assert!(!handle.is_finished());
+
+        handle.stop();
+        reader.join().expect("reading thread panicked");
+        handle.join().expect("playback thread panicked").expect("playback failed");
+    }
+
+    #[test]
+    fn release_dangling_key() {
+        let actions = vec![MacroAction {
+            offset: 5_000,
+            action: UserAction::Key(KeyAction { key: "LShift".to_string(), pressed: true })
+        }, MacroAction {
+            offset: 6_000,
+            action: UserAction::MouseButton(MouseButtonAction { button: 1, pressed: true })
+        }, MacroAction {
+            offset: 7_000,
+            action: UserAction::MouseButton(MouseButtonAction { button: 1, pressed: false })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 20_000, cursor_pos: (0, 0), ..Default::default() });
+
+        // Only the shift key was never released.
+        let outcome = towa.playback().expect("playback failed");
+        assert_eq!(outcome.released, 1);
+    }
+
+    #[test]
+    fn delay_after_click() {
+        let click = |offset, pressed| MacroAction {
+            offset,
+            action: UserAction::MouseButton(MouseButtonAction { button: 1, pressed })
+        };
+        let actions = vec![click(10_000, true), click(20_000, false), click(30_000, true), click(40_000, false)];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 50_000, cursor_pos: (0, 0), ..Default::default() });
+
+        let delay = Duration::from_millis(100);
+        let start = Instant::now();
+        towa.playback_with(&PlaybackOptions::new().post_click_delay(delay)).expect("playback failed");
+
+        // Both releases must have pushed the rest of the macro back.
+        assert!(start.elapsed() >= Duration::from_millis(50) + delay * 2);
+    }
+
+    #[test]
+    fn humanize_batch() {
+        let actions = (0..50)
+            .map(|i| MacroAction {
+                offset: i * 20_000,
+                action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: i % 2 == 0 })
+            })
+            .collect::<Vec<_>>();
+
+        let seeds = Macro::batch_seeds(42, 3);
+        assert_eq!(seeds.len(), 3);
+        assert_eq!(seeds, Macro::batch_seeds(42, 3));
+
+        let runs = seeds.iter()
+            .map(|seed| PlaybackOptions::new()
+                .humanize(Duration::from_millis(5), *seed)
+                .offsets(&actions, 1_000_000))
+            .collect::<Vec<_>>();
+
+        // Every run is distinct, but reproducible from its seed.
+        assert_ne!(runs[0], runs[1]);
+        assert_ne!(runs[1], runs[2]);
+        assert_ne!(runs[0], runs[2]);
+        assert_eq!(runs[0], PlaybackOptions::new()
+            .humanize(Duration::from_millis(5), seeds[0])
+            .offsets(&actions, 1_000_000));
+
+        for run in runs.iter() {
+            assert!(run.windows(2).all(|w| w[0] <= w[1]));
+            assert!(run.iter().zip(actions.iter()).all(|(at, a)| at.abs_diff(a.offset) <= 5_000));
+        }
     }
 
     #[test]
@@ -402,9 +3235,7 @@ mod test {
         let thread_towa = towa.clone();
         std::thread::spawn(move || {
             let _guard = thread_towa.record();
-            while thread_towa.is_recording() {
-                // Busy wait for the recording to finish.
-            }
+            thread_towa.wait_until_stopped();
         });
 
         sleep(Duration::from_secs(3));
@@ -424,9 +3255,7 @@ mod test {
         let thread_towa = towa.clone();
         std::thread::spawn(move || {
             let _guard = thread_towa.record();
-            while thread_towa.is_recording() {
-                // Busy wait for the recording to finish.
-            }
+            thread_towa.wait_until_stopped();
         });
 
         sleep(Duration::from_secs(3));
@@ -436,6 +3265,285 @@ mod test {
         towa.save("macro.json");
     }
 
+    #[test]
+    #[cfg(feature = "save")]
+    fn save_and_load_macro() {
+        let actions = (0..10)
+            .map(|i| MacroAction {
+                offset: i * 10_000,
+                action: UserAction::MouseMove(MouseMoveAction { x: i as i32, y: 0 })
+            })
+            .collect();
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 100_000, cursor_pos: (0, 0), ..Default::default() });
+
+        let path = std::env::temp_dir().join("macro-rs-round-trip.json");
+        towa.save(path.to_string_lossy());
+
+        let loaded = Macro::load(&path).expect("failed to load macro");
+        assert_eq!(loaded.actions.lock().unwrap().len(), 10);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    #[cfg(feature = "binary")]
+    fn save_and_load_binary_macro() {
+        let actions = (0..10)
+            .map(|i| MacroAction {
+                offset: i * 10_000,
+                action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: i % 2 == 0 })
+            })
+            .collect();
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 100_000, cursor_pos: (4, 2), ..Default::default() });
+
+        let path = std::env::temp_dir().join("macro-rs-round-trip.bin");
+        towa.save_binary(path.to_string_lossy());
+
+        let loaded = Macro::load_binary(&path).expect("failed to load macro");
+        assert_eq!(loaded.actions(), towa.actions());
+        assert_eq!(loaded.metadata.lock().unwrap().cursor_pos, (4, 2));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    #[cfg(feature = "compress")]
+    fn save_and_load_compressed_macro() {
+        // A few seconds of mouse movement, polled every few milliseconds.
+        let actions = (0..2000)
+            .map(|i| MacroAction {
+                offset: i * 4_000,
+                action: UserAction::MouseMove(MouseMoveAction { x: 500 + i as i32 % 300, y: 400 - i as i32 % 150 })
+            })
+            .collect();
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 8_000_000, cursor_pos: (500, 400), ..Default::default() });
+
+        let plain = std::env::temp_dir().join("macro-rs-plain.json");
+        let compressed = std::env::temp_dir().join("macro-rs-compressed.json.gz");
+        towa.save(plain.to_string_lossy());
+        towa.save_compressed(compressed.to_string_lossy());
+
+        // Both formats can be loaded with `load`.
+        let loaded = Macro::load(&compressed).expect("failed to load macro");
+        assert_eq!(loaded.actions(), towa.actions());
+        let loaded = Macro::load_compressed(&compressed).expect("failed to load macro");
+        assert_eq!(loaded.actions(), towa.actions());
+        assert_eq!(Macro::load(&plain).expect("failed to load macro").actions(), towa.actions());
+
+        let plain_size = std::fs::metadata(&plain).unwrap().len();
+        let compressed_size = std::fs::metadata(&compressed).unwrap().len();
+        println!("compressed {} bytes to {} bytes", plain_size, compressed_size);
+        assert!(compressed_size * 5 < plain_size);
+
+        std::fs::remove_file(plain).ok();
+        std::fs::remove_file(compressed).ok();
+    }
+
+    #[test]
+    #[cfg(feature = "save")]
+    fn save_pretty_macro() {
+        let actions = (0..3)
+            .map(|i| MacroAction {
+                offset: i * 10_000,
+                action: UserAction::MouseMove(MouseMoveAction { x: i as i32, y: 0 })
+            })
+            .collect();
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 30_000, cursor_pos: (0, 0), ..Default::default() });
+
+        let path = std::env::temp_dir().join("macro-rs-pretty.json");
+        towa.save_pretty(path.to_string_lossy());
+
+        let content = std::fs::read_to_string(&path).expect("failed to read macro");
+        assert!(content.lines().count() > 3);
+
+        let loaded = Macro::load(&path).expect("failed to load macro");
+        assert_eq!(loaded.actions(), towa.actions());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    #[cfg(feature = "save")]
+    fn load_missing_macro() {
+        let result = Macro::load("does/not/exist.json");
+        assert!(matches!(result, Err(LoadError::Io(_))));
+    }
+
+    #[test]
+    fn deserialize_without_input() {
+        let content = r#"{ "version": 2, "actions": [], "metadata": { "end": 10000, "cursor_pos": [0, 0] } }"#;
+        let towa: Macro = serde_json::from_str(content)
+            .expect("failed to deserialize macro");
+
+        // Neither deserializing nor cloning should connect to the input system.
+        assert!(towa.enigo.is_none());
+        assert!(towa.clone().enigo.is_none());
+    }
+
+    #[test]
+    fn clones_share_actions() {
+        let towa = Macro::from_parts(vec![], MacroMetadata { end: 10_000, cursor_pos: (0, 0), ..Default::default() });
+        let clone = towa.clone();
+
+        towa.actions.lock().unwrap().push(MacroAction {
+            offset: 0,
+            action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true })
+        });
+        assert_eq!(clone.actions().len(), 1);
+    }
+
+    #[test]
+    fn deep_copy_is_independent() {
+        let actions = vec![MacroAction {
+            offset: 0,
+            action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata { end: 10_000, cursor_pos: (0, 0), ..Default::default() });
+        let copy = towa.deep_copy();
+
+        towa.trim_to(Duration::ZERO, Duration::from_millis(5));
+        towa.actions.lock().unwrap().clear();
+        assert_eq!(copy.actions().len(), 1);
+        assert_eq!(copy.duration(), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn deserialize_scroll() {
+        let content = r#"[
+            { "action": { "MouseScroll": { "delta": 3 } }, "offset": 0 },
+            { "action": { "MouseScroll": { "delta": -1, "axis": "Horizontal" } }, "offset": 5000 }
+        ]"#;
+        let actions: Vec<MacroAction> = serde_json::from_str(content)
+            .expect("failed to deserialize scroll actions");
+
+        let axes = actions.iter()
+            .map(|a| match &a.action {
+                UserAction::MouseScroll(scroll) => (scroll.delta, scroll.axis),
+                _ => unreachable!()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(axes, vec![(3, ScrollAxis::Vertical), (-1, ScrollAxis::Horizontal)]);
+    }
+
+    #[test]
+    fn deserialize_shuffled_actions() {
+        let content = r#"{
+            "version": 2,
+            "actions": [
+                { "action": { "Key": { "key": "B", "pressed": true } }, "offset": 300 },
+                { "action": { "Key": { "key": "A", "pressed": true } }, "offset": 100 },
+                { "action": { "Key": { "key": "A", "pressed": false } }, "offset": 100 },
+                { "action": { "Key": { "key": "B", "pressed": false } }, "offset": 200 }
+            ],
+            "metadata": { "end": 400, "cursor_pos": [0, 0] }
+        }"#;
+        let towa: Macro = serde_json::from_str(content).expect("failed to deserialize macro");
+
+        let order = towa.actions().into_iter()
+            .map(|a| match a.kind {
+                ActionKind::Key { key, pressed, .. } => (key, pressed),
+                _ => unreachable!()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![
+            ("A".to_string(), true),
+            ("A".to_string(), false),
+            ("B".to_string(), false),
+            ("B".to_string(), true)
+        ]);
+    }
+
+    #[test]
+    fn offsets_below_a_millisecond() {
+        let towa = Macro::from_parts(vec![], MacroMetadata::default());
+        *towa.is_recording.lock() = true;
+        let start = Instant::now();
+        *towa.start_time.lock().unwrap() = start;
+        let recorder = Recorder {
+            start,
+            actions: towa.actions.clone(),
+            is_recording: towa.is_recording.clone(),
+            start_time: towa.start_time.clone(),
+            metadata: towa.metadata.clone()
+        };
+
+        // Two key presses recorded less than a millisecond apart.
+        let key = |key: &str| UserAction::Key(KeyAction { key: key.to_string(), pressed: true });
+        recorder.push(key("A"));
+        sleep(Duration::from_micros(600));
+        recorder.push(key("B"));
+        recorder.stop();
+
+        let serialized = serde_json::to_string(&towa).expect("failed to serialize macro");
+        let loaded: Macro = serde_json::from_str(&serialized).expect("failed to deserialize macro");
+
+        let offsets = |towa: &Macro| towa.actions.lock().unwrap().iter().map(|a| a.offset).collect::<Vec<_>>();
+        let recorded = offsets(&towa);
+        assert!(recorded[1] - recorded[0] >= 600, "recorded offsets were {:?}", recorded);
+        assert_eq!(offsets(&loaded), recorded);
+        assert_eq!(loaded.metadata.lock().unwrap().end, towa.metadata.lock().unwrap().end);
+    }
+
+    #[test]
+    fn upgrade_millisecond_offsets() {
+        let content = r#"{
+            "actions": [{ "action": { "Key": { "key": "A", "pressed": true } }, "offset": 12 }],
+            "metadata": { "end": 20, "cursor_pos": [0, 0] }
+        }"#;
+        let towa: Macro = serde_json::from_str(content)
+            .expect("failed to deserialize macro");
+
+        assert_eq!(towa.actions.lock().unwrap()[0].offset, 12_000);
+        assert_eq!(towa.metadata.lock().unwrap().end, 20_000);
+
+        // Saving the macro again stores the new version.
+        let serialized = serde_json::to_value(&towa)
+            .expect("failed to serialize macro");
+        assert_eq!(serialized["version"], FORMAT_VERSION);
+    }
+
+    #[test]
+    fn reject_overflowing_millisecond_offsets() {
+        let content = format!(r#"{{
+            "actions": [{{ "action": {{ "Key": {{ "key": "A", "pressed": true }} }}, "offset": {} }}],
+            "metadata": {{ "end": 20, "cursor_pos": [0, 0] }}
+        }}"#, u64::MAX / 1000 + 1);
+
+        let error = serde_json::from_str::<Macro>(&content).expect_err("offset should overflow");
+        assert!(error.to_string().contains("offset out of range"));
+    }
+
+    #[test]
+    fn deserialize_map_and_seq() {
+        let towa = Macro::from_parts(vec![MacroAction {
+            offset: 1_500,
+            action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true })
+        }], MacroMetadata { end: 2_000, cursor_pos: (3, 4), ..Default::default() });
+
+        // Structs are maps in self-describing formats...
+        let map = serde_json::to_string(&towa).expect("failed to serialize macro");
+        let from_map: Macro = serde_json::from_str(&map).expect("failed to deserialize map");
+        assert_eq!(from_map.actions(), towa.actions());
+
+        // ...and sequences of their fields in formats which aren't.
+        let seq = r#"[2, [{ "action": { "Key": { "key": "A", "pressed": true } }, "offset": 1500 }], { "end": 2000, "cursor_pos": [3, 4] }]"#;
+        let from_seq: Macro = serde_json::from_str(seq).expect("failed to deserialize sequence");
+        assert_eq!(from_seq.actions(), towa.actions());
+        assert_eq!(from_seq.metadata.lock().unwrap().cursor_pos, (3, 4));
+
+        // Missing and repeated fields are errors, not panics.
+        assert!(serde_json::from_str::<Macro>("[2, []]").is_err());
+        assert!(serde_json::from_str::<Macro>(r#"{ "actions": [], "actions": [] }"#).is_err());
+        assert!(serde_json::from_str::<Macro>(r#"{ "actions": [] }"#).is_err());
+    }
+
+    #[test]
+    fn reject_newer_version() {
+        let content = r#"{ "version": 99, "actions": [{ "action": { "Teleport": {} }, "offset": 0 }], "metadata": { "end": 0, "cursor_pos": [0, 0] } }"#;
+        let error = serde_json::from_str::<Macro>(content).unwrap_err();
+        assert!(error.to_string().contains("version 99 is newer"), "{}", error);
+    }
+
     #[test]
     fn load_macro() {
         let content = std::fs::read_to_string("macro.json")
@@ -444,6 +3552,6 @@ mod test {
             .expect("failed to deserialize macro");
 
         sleep(Duration::from_secs(1));
-        loaded_macro.playback();
+        loaded_macro.playback().expect("playback failed");
     }
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use enigo::Key;
+use crate::utils;
+
+/// A table of key names and the keys they're played back as.
+///
+/// Keys inserted into the table take priority over the built-in
+/// mappings, which are still used for every other key. This lets
+/// keys which have no built-in mapping be played back, and lets
+/// keys be swapped for others without editing the macro.
+#[derive(Debug, Default, Clone)]
+pub struct RemapTable {
+    overrides: HashMap<String, Key>
+}
+
+impl RemapTable {
+    /// Creates a table which only uses the built-in mappings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plays the key named `name` back as `key`.
+    ///
+    /// Returns the key which was previously inserted for `name`, if any.
+    pub fn insert<S: Into<String>>(&mut self, name: S, key: Key) -> Option<Key> {
+        self.overrides.insert(name.into(), key)
+    }
+
+    /// Removes the key inserted for `name`, restoring the built-in mapping.
+    pub fn remove(&mut self, name: &str) -> Option<Key> {
+        self.overrides.remove(name)
+    }
+
+    /// Returns the key which the key named `name` is played back as.
+    pub fn get(&self, name: &str) -> Option<Key> {
+        self.overrides.get(name)
+            .copied()
+            .or_else(|| utils::remap(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn override_unmapped_key() {
+        let mut table = RemapTable::new();
+        assert_eq!(table.get("NotAKey"), None);
+
+        table.insert("NotAKey", Key::Unicode('x'));
+        assert_eq!(table.get("NotAKey"), Some(Key::Unicode('x')));
+
+        // Built-in mappings are still used for other keys.
+        assert_eq!(table.get("Escape"), Some(Key::Escape));
+
+        table.remove("NotAKey");
+        assert_eq!(table.get("NotAKey"), None);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::Write;
+use crate::macros::{KeyAction, MacroAction, MacroMetadata, MouseButtonAction, MouseMoveAction, MouseScrollAction, ScrollAxis, UserAction};
+use crate::{Macro, MouseMode, ScriptError};
+use crate::utils;
+
+/// Formats an offset in microseconds, using milliseconds where possible.
+fn format_offset(offset: u64) -> String {
+    if offset.is_multiple_of(1000) {
+        format!("{}ms", offset / 1000)
+    } else {
+        format!("{}us", offset)
+    }
+}
+
+/// Parses an offset written by `format_offset` into microseconds.
+fn parse_offset(offset: &str) -> Option<u64> {
+    if let Some(millis) = offset.strip_suffix("ms") {
+        millis.parse::<u64>().ok()?.checked_mul(1000)
+    } else if let Some(micros) = offset.strip_suffix("us") {
+        micros.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Parses the state of a key or button.
+fn parse_pressed(state: &str) -> Option<bool> {
+    match state {
+        "down" => Some(true),
+        "up" => Some(false),
+        _ => None
+    }
+}
+
+/// Describes an action as the rest of its line in a script.
+fn describe(action: &UserAction) -> String {
+    let state = |pressed| if pressed { "down" } else { "up" };
+
+    match action {
+        UserAction::MouseMove(mouse) => format!("mouse move {} {}", mouse.x, mouse.y),
+        UserAction::MouseButton(mouse) => match utils::button_name(mouse.button) {
+            Some(name) => format!("button {} {}", name, state(mouse.pressed)),
+            None => format!("button {} {}", mouse.button, state(mouse.pressed))
+        },
+        UserAction::MouseScroll(scroll) => match scroll.axis {
+            ScrollAxis::Vertical => format!("scroll {}", scroll.delta),
+            ScrollAxis::Horizontal => format!("scroll {} horizontal", scroll.delta)
+        },
+        UserAction::Key(key) => format!("key {} {}", key.key, state(key.pressed))
+    }
+}
+
+/// Parses the rest of a line in a script into an action.
+fn parse_action(words: &[&str]) -> Result<UserAction, String> {
+    let number = |word: &str| word.parse::<i32>()
+        .map_err(|_| format!("`{}` is not a number", word));
+    let pressed = |word: &str| parse_pressed(word)
+        .ok_or_else(|| format!("expected `down` or `up`, found `{}`", word));
+
+    match words {
+        ["mouse", "move", x, y] => Ok(UserAction::MouseMove(MouseMoveAction {
+            x: number(x)?,
+            y: number(y)?
+        })),
+        ["button", button, state] => {
+            let button = utils::button_from_name(button)
+                .or_else(|| button.parse().ok())
+                .ok_or_else(|| format!("unknown mouse button `{}`", button))?;
+            Ok(UserAction::MouseButton(MouseButtonAction { button, pressed: pressed(state)? }))
+        }
+        ["scroll", delta] | ["scroll", delta, "vertical"] => Ok(UserAction::MouseScroll(MouseScrollAction {
+            delta: number(delta)?,
+            axis: ScrollAxis::Vertical
+        })),
+        ["scroll", delta, "horizontal"] => Ok(UserAction::MouseScroll(MouseScrollAction {
+            delta: number(delta)?,
+            axis: ScrollAxis::Horizontal
+        })),
+        ["key", key, state] => Ok(UserAction::Key(KeyAction {
+            key: key.to_string(),
+            pressed: pressed(state)?
+        })),
+        _ => Err(format!("unknown action `{}`", words.join(" ")))
+    }
+}
+
+impl Macro {
+    /// Writes this macro as a script which can be read and edited by hand.
+    ///
+    /// Each line of a script is either a setting or an action. The
+    /// settings are the starting cursor position, the mouse mode, and
+    /// a `held` line for each key held down when recording started.
+    /// Actions start with their offset, in milliseconds (`ms`) or
+    /// microseconds (`us`), followed by what the action does:
+    ///
+    /// ```text
+    /// # Settings
+    /// cursor 100 200
+    /// mode absolute
+    /// held LShift
+    /// # Actions
+    /// 0ms key A down
+    /// 120ms mouse move 5 -3
+    /// 200ms button left up
+    /// 250ms scroll -1 horizontal
+    /// 300ms end
+    /// ```
+    ///
+    /// Blank lines and anything after a `#` are ignored.
+    /// Use `Macro::from_script` to read the script back.
+    pub fn to_script(&self) -> String {
+        let actions = utils::lock(&self.actions);
+        let metadata = utils::lock(&self.metadata);
+        let mut script = String::new();
+
+        // Writing to a `String` never fails.
+        let (x, y) = metadata.cursor_pos;
+        writeln!(script, "cursor {} {}", x, y).unwrap();
+        match metadata.mouse_mode {
+            MouseMode::Absolute => writeln!(script, "mode absolute").unwrap(),
+            MouseMode::Relative => writeln!(script, "mode relative").unwrap()
+        }
+        for key in &metadata.held_keys {
+            writeln!(script, "held {}", key).unwrap();
+        }
+
+        for action in actions.iter() {
+            writeln!(script, "{} {}", format_offset(action.offset), describe(&action.action)).unwrap();
+        }
+        writeln!(script, "{} end", format_offset(metadata.end)).unwrap();
+
+        script
+    }
+
+    /// Reads a macro from a script written by `Macro::to_script`.
+    ///
+    /// If the script has no `end` line, the macro ends with its last action.
+    pub fn from_script(script: &str) -> Result<Macro, ScriptError> {
+        let mut actions = Vec::new();
+        let mut metadata = MacroMetadata::default();
+        let mut end = None;
+
+        for (index, line) in script.lines().enumerate() {
+            let number = index + 1;
+            let error = |message: String| ScriptError::new(number, message);
+
+            // Strip comments and skip blank lines.
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+
+            match words.as_slice() {
+                [] => {}
+                ["cursor", x, y] => {
+                    let x = x.parse().map_err(|_| error(format!("`{}` is not a number", x)))?;
+                    let y = y.parse().map_err(|_| error(format!("`{}` is not a number", y)))?;
+                    metadata.cursor_pos = (x, y);
+                }
+                ["mode", "absolute"] => metadata.mouse_mode = MouseMode::Absolute,
+                ["mode", "relative"] => metadata.mouse_mode = MouseMode::Relative,
+                ["held", key] => metadata.held_keys.push(key.to_string()),
+                [offset, rest @ ..] => {
+                    let offset = parse_offset(offset)
+                        .ok_or_else(|| error(format!("unknown line `{}`", line.trim())))?;
+
+                    if rest == ["end"] {
+                        end = Some(offset);
+                    } else {
+                        let action = parse_action(rest).map_err(error)?;
+                        actions.push(MacroAction { offset, action });
+                    }
+                }
+            }
+        }
+
+        // Keep actions in time order, even if the script wasn't.
+        actions.sort_by_key(|a| a.offset);
+        metadata.end = end
+            .or_else(|| actions.last().map(|a| a.offset))
+            .unwrap_or(0);
+
+        Ok(Macro::from_parts(actions, metadata))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip_script() {
+        let actions = vec![
+            MacroAction { offset: 0, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            MacroAction { offset: 120_000, action: UserAction::MouseMove(MouseMoveAction { x: 5, y: -3 }) },
+            MacroAction { offset: 200_500, action: UserAction::MouseButton(MouseButtonAction { button: 1, pressed: false }) },
+            MacroAction { offset: 250_000, action: UserAction::MouseScroll(MouseScrollAction { delta: -1, axis: ScrollAxis::Horizontal }) },
+        ];
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 300_000, cursor_pos: (100, 200), mouse_mode: MouseMode::Relative, ..Default::default() });
+
+        let script = towa.to_script();
+        assert!(script.contains("200500us button left up"), "{}", script);
+
+        let loaded = Macro::from_script(&script).expect("failed to parse script");
+        assert_eq!(loaded.actions(), towa.actions());
+        assert_eq!(loaded.duration(), towa.duration());
+        assert_eq!(loaded.mouse_mode(), MouseMode::Relative);
+        assert_eq!(loaded.metadata.lock().unwrap().cursor_pos, (100, 200));
+    }
+
+    #[test]
+    fn report_script_errors() {
+        let script = "# A comment\n\n0ms key A down\n10ms key A sideways\n";
+        let error = Macro::from_script(script).unwrap_err();
+        assert_eq!(error.line(), 4);
+
+        let error = Macro::from_script("soon key A down").unwrap_err();
+        assert_eq!(error.line(), 1);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use crate::macros::UserAction;
+use crate::Macro;
+use crate::utils;
+
+/// An overview of what a macro does, without its actions.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MacroSummary {
+    /// How long the macro takes to play back.
+    pub duration: Duration,
+    /// The number of key presses and releases.
+    pub key_events: usize,
+    /// The number of mouse button presses and releases.
+    pub button_events: usize,
+    /// The number of mouse moves.
+    pub mouse_moves: usize,
+    /// The number of mouse wheel scrolls.
+    pub scrolls: usize,
+    /// The number of different keys pressed or released.
+    pub distinct_keys: usize
+}
+
+impl Macro {
+    /// Counts the actions of this macro by kind.
+    pub fn summary(&self) -> MacroSummary {
+        let actions = utils::lock(&self.actions);
+        let mut summary = MacroSummary {
+            duration: self.duration(),
+            ..Default::default()
+        };
+
+        let mut keys = HashSet::new();
+        for action in actions.iter() {
+            match &action.action {
+                UserAction::MouseMove(_) => summary.mouse_moves += 1,
+                UserAction::MouseButton(_) => summary.button_events += 1,
+                UserAction::MouseScroll(_) => summary.scrolls += 1,
+                UserAction::Key(key) => {
+                    summary.key_events += 1;
+                    keys.insert(&key.key);
+                }
+            }
+        }
+        summary.distinct_keys = keys.len();
+
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::macros::{KeyAction, MacroAction, MacroMetadata, MouseButtonAction, MouseMoveAction};
+
+    #[test]
+    fn summarize_actions() {
+        let key = |offset, key: &str, pressed| MacroAction {
+            offset,
+            action: UserAction::Key(KeyAction { key: key.to_string(), pressed })
+        };
+        let actions = vec![
+            key(0, "A", true),
+            key(10_000, "A", false),
+            key(20_000, "LShift", true),
+            MacroAction { offset: 30_000, action: UserAction::MouseMove(MouseMoveAction { x: 1, y: 2 }) },
+            MacroAction { offset: 40_000, action: UserAction::MouseMove(MouseMoveAction { x: 3, y: 4 }) },
+            MacroAction { offset: 50_000, action: UserAction::MouseButton(MouseButtonAction { button: 1, pressed: true }) },
+            key(60_000, "LShift", false)
+        ];
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 70_000, cursor_pos: (0, 0), ..Default::default() });
+
+        assert_eq!(towa.summary(), MacroSummary {
+            duration: Duration::from_millis(70),
+            key_events: 4,
+            button_events: 1,
+            mouse_moves: 2,
+            scrolls: 0,
+            distinct_keys: 2
+        });
+    }
+}
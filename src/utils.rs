@@ -1,13 +1,31 @@
 use std::str::FromStr;
-use device_query::Keycode;
-use enigo::Key;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use device_query::{Keycode, MouseButton};
+use enigo::{Button, Key};
+
+/// Locks a mutex, even if a thread panicked while holding it.
+///
+/// A macro's shared state is never left half-updated, so a panic in
+/// another thread, such as in a recording callback, shouldn't make
+/// the macro unusable.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
 /// Remaps a key name from `device_query` to `enigo`'s `Key`.
 /// 
 /// Taken from: https://github.com/lopo12123/toca/blob/master/src/mapper.rs
-pub(crate) fn remap(key_name: &String) -> Option<Key> {
+pub(crate) fn remap(key_name: &str) -> Option<Key> {
+    // Handle keys which `device_query` has no `Keycode` for.
+    // These only appear in hand-written or edited macros.
+    match key_name {
+        "Menu" | "Apps" => return menu_key(),
+        "Fn" | "Function" => return function_key(),
+        _ => {}
+    }
+
     // Parse the key name into a `Keycode`.
-    let Ok(keycode) = Keycode::from_str(key_name.as_str()) else {
+    let Ok(keycode) = Keycode::from_str(key_name) else {
         return None;
     };
 
@@ -70,14 +88,15 @@ pub(crate) fn remap(key_name: &String) -> Option<Key> {
         Keycode::LShift | Keycode::RShift => Some(Key::Shift),
         Keycode::LControl | Keycode::RControl => Some(Key::Control),
         Keycode::LAlt | Keycode::RAlt => Some(Key::Alt),
+        Keycode::LMeta | Keycode::RMeta | Keycode::Command | Keycode::RCommand => Some(Key::Meta),
         Keycode::Space => Some(Key::Space),
         Keycode::Up => Some(Key::UpArrow),
         Keycode::Right => Some(Key::RightArrow),
         Keycode::Down => Some(Key::DownArrow),
         Keycode::Left => Some(Key::LeftArrow),
-        Keycode::Enter => Some(Key::Return),
+        Keycode::Enter | Keycode::NumpadEnter => Some(Key::Return),
         Keycode::Backspace => Some(Key::Backspace),
-        // Keycode::Insert => None,
+        Keycode::Insert => insert_key(),
         Keycode::Delete => Some(Key::Delete),
         Keycode::Home => Some(Key::Home),
         Keycode::PageUp => Some(Key::PageUp),
@@ -106,10 +125,196 @@ pub(crate) fn remap(key_name: &String) -> Option<Key> {
         Keycode::Numpad7 => Some(Key::Numpad7),
         Keycode::Numpad8 => Some(Key::Numpad8),
         Keycode::Numpad9 => Some(Key::Numpad9),
+        Keycode::NumpadAdd => Some(Key::Add),
+        Keycode::NumpadMultiply => Some(Key::Multiply),
+        _ => None
+    }
+}
+
+/// Remaps a mouse button number from `device_query` to `enigo`'s `Button`.
+///
+/// `device_query` numbers buttons the way the platform does:
+///
+/// | Button | Windows   | Linux (X11)  | macOS  |
+/// |--------|-----------|--------------|--------|
+/// | 1      | `Left`    | `Left`       | `Left` |
+/// | 2      | `Right`   | `Middle`     | `Right` |
+/// | 3      | `Middle`  | `Right`      | `Middle` |
+/// | 4      | `Back`    | `ScrollUp`   | -      |
+/// | 5      | `Forward` | `ScrollDown` | -      |
+///
+/// `device_query` never reports any other buttons, so anything else
+/// (such as from an edited macro) has no mapping.
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub(crate) fn remap_button(button: MouseButton) -> Option<Button> {
+    match button {
+        1 => Some(Button::Left),
+        2 => Some(Button::Right),
+        3 => Some(Button::Middle),
+        4 => Some(Button::Back),
+        5 => Some(Button::Forward),
         _ => None
     }
 }
 
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn remap_button(button: MouseButton) -> Option<Button> {
+    match button {
+        1 => Some(Button::Left),
+        2 => Some(Button::Middle),
+        3 => Some(Button::Right),
+        4 => Some(Button::ScrollUp),
+        5 => Some(Button::ScrollDown),
+        _ => None
+    }
+}
+
+/// Returns a readable name for a mouse button, such as `left`.
+pub(crate) fn button_name(button: MouseButton) -> Option<&'static str> {
+    match remap_button(button)? {
+        Button::Left => Some("left"),
+        Button::Right => Some("right"),
+        Button::Middle => Some("middle"),
+        Button::Back => Some("back"),
+        Button::Forward => Some("forward"),
+        Button::ScrollUp => Some("scroll-up"),
+        Button::ScrollDown => Some("scroll-down"),
+        _ => None
+    }
+}
+
+/// Returns the mouse button with the given name from `button_name`.
+pub(crate) fn button_from_name(name: &str) -> Option<MouseButton> {
+    // `device_query` only reports buttons 1 to 5.
+    (1..=5).find(|button| button_name(*button) == Some(name))
+}
+
+/// Returns the application (context menu) key.
+///
+/// Windows exposes this as `Apps` and X11 as the `Menu` keysym.
+/// There is no reliable equivalent on macOS.
+#[cfg(target_os = "windows")]
+fn menu_key() -> Option<Key> {
+    Some(Key::Apps)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn menu_key() -> Option<Key> {
+    // XK_Menu
+    Some(Key::Other(0xff67))
+}
+
+#[cfg(target_os = "macos")]
+fn menu_key() -> Option<Key> {
+    None
+}
+
+/// Returns the insert key.
+///
+/// Mac keyboards have no insert key, so it can't be simulated on macOS.
+#[cfg(not(target_os = "macos"))]
+fn insert_key() -> Option<Key> {
+    Some(Key::Insert)
+}
+
+#[cfg(target_os = "macos")]
+fn insert_key() -> Option<Key> {
+    None
+}
+
+/// Returns the laptop function (Fn) key.
+///
+/// On most keyboards the Fn key is handled by the keyboard firmware
+/// and is never seen by the operating system, so it can only be
+/// simulated on macOS.
+#[cfg(target_os = "macos")]
+fn function_key() -> Option<Key> {
+    Some(Key::Function)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn function_key() -> Option<Key> {
+    None
+}
+
+/// Returns the key which types the given character on a US keyboard
+/// layout, and whether shift has to be held to type it.
+pub(crate) fn char_key(c: char) -> Option<(Keycode, bool)> {
+    if c.is_ascii_alphabetic() {
+        let name = c.to_ascii_uppercase().to_string();
+        return Keycode::from_str(&name).ok().map(|key| (key, c.is_ascii_uppercase()));
+    }
+    if let Some(digit) = c.to_digit(10) {
+        return Keycode::from_str(&format!("Key{}", digit)).ok().map(|key| (key, false));
+    }
+
+    let key = match c {
+        ' ' => (Keycode::Space, false),
+        '\n' => (Keycode::Enter, false),
+        '\t' => (Keycode::Tab, false),
+        '`' => (Keycode::Grave, false),
+        '~' => (Keycode::Grave, true),
+        '-' => (Keycode::Minus, false),
+        '_' => (Keycode::Minus, true),
+        '=' => (Keycode::Equal, false),
+        '+' => (Keycode::Equal, true),
+        '[' => (Keycode::LeftBracket, false),
+        '{' => (Keycode::LeftBracket, true),
+        ']' => (Keycode::RightBracket, false),
+        '}' => (Keycode::RightBracket, true),
+        '\\' => (Keycode::BackSlash, false),
+        '|' => (Keycode::BackSlash, true),
+        ';' => (Keycode::Semicolon, false),
+        ':' => (Keycode::Semicolon, true),
+        '\'' => (Keycode::Apostrophe, false),
+        '"' => (Keycode::Apostrophe, true),
+        ',' => (Keycode::Comma, false),
+        '<' => (Keycode::Comma, true),
+        '.' => (Keycode::Dot, false),
+        '>' => (Keycode::Dot, true),
+        '/' => (Keycode::Slash, false),
+        '?' => (Keycode::Slash, true),
+        '!' => (Keycode::Key1, true),
+        '@' => (Keycode::Key2, true),
+        '#' => (Keycode::Key3, true),
+        '$' => (Keycode::Key4, true),
+        '%' => (Keycode::Key5, true),
+        '^' => (Keycode::Key6, true),
+        '&' => (Keycode::Key7, true),
+        '*' => (Keycode::Key8, true),
+        '(' => (Keycode::Key9, true),
+        ')' => (Keycode::Key0, true),
+        _ => return None
+    };
+
+    Some(key)
+}
+
+/// Returns the scale factor of the display, if it can be found.
+///
+/// Only the `GDK_SCALE` and `QT_SCALE_FACTOR` environment variables are
+/// checked, so the scale factor often can't be found and has to be set
+/// with `MacroBuilder::display_scale` instead.
+pub(crate) fn display_scale() -> Option<f64> {
+    ["GDK_SCALE", "QT_SCALE_FACTOR"].iter()
+        .filter_map(|name| std::env::var(name).ok()?.trim().parse::<f64>().ok())
+        .find(|scale| *scale > 0.0 && scale.is_finite())
+}
+
+/// Returns the title of the window which has focus, if it can be found.
+#[cfg(feature = "window-title")]
+pub(crate) fn window_title() -> Option<String> {
+    active_win_pos_rs::get_active_window()
+        .ok()
+        .map(|window| window.title)
+}
+
+/// Returns nothing, since finding the focused window isn't enabled.
+#[cfg(not(feature = "window-title"))]
+pub(crate) fn window_title() -> Option<String> {
+    None
+}
+
 /// Serializes a keycode to a string representation.
 pub(crate) fn to_string(keycode: &Keycode) -> String {
     match keycode {
@@ -226,4 +431,123 @@ pub(crate) fn to_string(keycode: &Keycode) -> String {
         Keycode::Dot => "Dot",
         Keycode::Slash => "Slash",
     }.to_string()
-}
\ No newline at end of file
+}
+
+/// Accumulates fractional mouse movement so that sub-pixel
+/// remainders carry over to the next move instead of being truncated.
+///
+/// The running total is kept in floating point and only the
+/// difference between its rounded value and what has already been
+/// emitted is returned, so rounding error never compounds.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SubPixel {
+    total: (f64, f64),
+    emitted: (i64, i64)
+}
+
+impl SubPixel {
+    /// Adds a fractional delta and returns the whole-pixel
+    /// displacement that should be applied for it.
+    pub(crate) fn step(&mut self, dx: f64, dy: f64) -> (i32, i32) {
+        self.total.0 += dx;
+        self.total.1 += dy;
+
+        let x = self.total.0.round() as i64;
+        let y = self.total.1.round() as i64;
+        let delta = ((x - self.emitted.0) as i32, (y - self.emitted.1) as i32);
+        self.emitted = (x, y);
+
+        delta
+    }
+}
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+///
+/// This is only used to vary playback; it is not suitable for
+/// anything security related.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// Returns the next random number.
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number in `0..bound`.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sub_pixel_carries_remainder() {
+        let mut accumulator = SubPixel::default();
+        let (mut x, mut y) = (0, 0);
+
+        // Each delta rounds to zero on its own; only the carried
+        // remainder makes the cursor move at all.
+        for i in 0..1000 {
+            let dx = if i % 2 == 0 { 0.25 } else { 0.35 };
+            let (step_x, step_y) = accumulator.step(dx, -0.45);
+            x += step_x;
+            y += step_y;
+        }
+
+        assert_eq!(x, 300);
+        assert_eq!(y, -450);
+    }
+
+    #[test]
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn remap_buttons() {
+        assert_eq!(remap_button(1), Some(Button::Left));
+        assert_eq!(remap_button(2), Some(Button::Right));
+        assert_eq!(remap_button(3), Some(Button::Middle));
+        assert_eq!(remap_button(4), Some(Button::Back));
+        assert_eq!(remap_button(5), Some(Button::Forward));
+        assert_eq!(remap_button(0), None);
+        assert_eq!(remap_button(6), None);
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn remap_buttons() {
+        assert_eq!(remap_button(1), Some(Button::Left));
+        assert_eq!(remap_button(2), Some(Button::Middle));
+        assert_eq!(remap_button(3), Some(Button::Right));
+        assert_eq!(remap_button(4), Some(Button::ScrollUp));
+        assert_eq!(remap_button(5), Some(Button::ScrollDown));
+        assert_eq!(remap_button(0), None);
+        assert_eq!(remap_button(6), None);
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn remap_menu_key() {
+        assert!(remap("Menu").is_some());
+        assert!(remap("Apps").is_some());
+    }
+
+    #[test]
+    fn remap_numpad_and_meta_keys() {
+        assert_eq!(remap("NumpadEnter"), Some(Key::Return));
+        assert_eq!(remap("NumpadAdd"), Some(Key::Add));
+        assert_eq!(remap("NumpadMultiply"), Some(Key::Multiply));
+        assert_eq!(remap("LMeta"), Some(Key::Meta));
+        assert_eq!(remap("RMeta"), Some(Key::Meta));
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+use device_query::MouseButton;
+use crate::macros::UserAction;
+use crate::utils;
+use crate::Macro;
+
+/// A problem with a macro which would stop it from playing back as recorded.
+///
+/// Actions are referred to by their index in `Macro::actions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// A key has no key to play back as, so it would be skipped.
+    UnmappedKey { index: usize, key: String },
+    /// A mouse button is unknown, so it would be skipped.
+    UnknownButton { index: usize, button: MouseButton },
+    /// An action happens before the action preceding it.
+    OutOfOrder { index: usize },
+    /// The macro ends before its last action, which would never be played.
+    EndBeforeLastAction { end: Duration, last: Duration }
+}
+
+impl Macro {
+    /// Checks that every action of this macro can be played back.
+    ///
+    /// Keys are looked up in this macro's remap table. This doesn't
+    /// connect to the input system, so it's safe to run headless.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let actions = utils::lock(&self.actions);
+        let metadata = utils::lock(&self.metadata);
+        let mut issues = Vec::new();
+
+        let mut previous = 0;
+        for (index, action) in actions.iter().enumerate() {
+            match &action.action {
+                UserAction::Key(key) if self.remap.get(&key.key).is_none() => {
+                    issues.push(ValidationIssue::UnmappedKey { index, key: key.key.clone() });
+                }
+                UserAction::MouseButton(mouse) if utils::remap_button(mouse.button).is_none() => {
+                    issues.push(ValidationIssue::UnknownButton { index, button: mouse.button });
+                }
+                _ => {}
+            }
+
+            if action.offset < previous {
+                issues.push(ValidationIssue::OutOfOrder { index });
+            }
+            previous = previous.max(action.offset);
+        }
+
+        // The last action is the latest one, even if they're out of order.
+        if previous > metadata.end {
+            issues.push(ValidationIssue::EndBeforeLastAction {
+                end: Duration::from_micros(metadata.end),
+                last: Duration::from_micros(previous)
+            });
+        }
+
+        issues
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::macros::{KeyAction, MacroAction, MacroMetadata, MouseButtonAction};
+
+    #[test]
+    fn report_issues() {
+        let actions = vec![
+            MacroAction { offset: 0, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            MacroAction { offset: 20_000, action: UserAction::Key(KeyAction { key: "NotAKey".to_string(), pressed: true }) },
+            MacroAction { offset: 10_000, action: UserAction::MouseButton(MouseButtonAction { button: 99, pressed: true }) },
+        ];
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 15_000, cursor_pos: (0, 0), ..Default::default() });
+
+        assert_eq!(towa.validate(), vec![
+            ValidationIssue::UnmappedKey { index: 1, key: "NotAKey".to_string() },
+            ValidationIssue::UnknownButton { index: 2, button: 99 },
+            ValidationIssue::OutOfOrder { index: 2 },
+            ValidationIssue::EndBeforeLastAction { end: Duration::from_millis(15), last: Duration::from_millis(20) }
+        ]);
+    }
+
+    #[test]
+    fn valid_macro_has_no_issues() {
+        let actions = vec![
+            MacroAction { offset: 0, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true }) },
+            MacroAction { offset: 10_000, action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: false }) },
+        ];
+        let towa = Macro::from_parts(actions, MacroMetadata { end: 10_000, cursor_pos: (0, 0), ..Default::default() });
+
+        assert!(towa.validate().is_empty());
+    }
+}
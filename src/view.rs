@@ -0,0 +1,211 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use device_query::MouseButton;
+use crate::macros::{MacroAction, ScrollAxis, UserAction};
+use crate::{Macro, RemapTable};
+use crate::utils;
+
+/// A read-only view of a single recorded action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionView {
+    /// The time since the start of the macro when the action occurred.
+    pub offset: Duration,
+    /// What the action does.
+    pub kind: ActionKind
+}
+
+/// The kind of a recorded action, and the data associated with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionKind {
+    /// The cursor moved to the given position, or by the given
+    /// distance if the macro uses `MouseMode::Relative`.
+    MouseMove { x: i32, y: i32 },
+    /// A mouse button was pressed or released.
+    MouseButton { button: MouseButton, pressed: bool },
+    /// The mouse wheel was scrolled by `delta` steps.
+    MouseScroll { delta: i32, horizontal: bool },
+    /// A key was pressed or released.
+    ///
+    /// The key is named after its `Keycode`. `mapped` is whether the
+    /// macro's remap table has a key for it, so that it's played back.
+    Key { key: String, pressed: bool, mapped: bool }
+}
+
+impl ActionView {
+    /// Creates a view of `action`, looking up keys in `remap`.
+    pub(crate) fn new(action: &MacroAction, remap: &RemapTable) -> Self {
+        let kind = match &action.action {
+            UserAction::MouseMove(mouse) => ActionKind::MouseMove {
+                x: mouse.x,
+                y: mouse.y
+            },
+            UserAction::MouseButton(mouse) => ActionKind::MouseButton {
+                button: mouse.button,
+                pressed: mouse.pressed
+            },
+            UserAction::MouseScroll(scroll) => ActionKind::MouseScroll {
+                delta: scroll.delta,
+                horizontal: scroll.axis == ScrollAxis::Horizontal
+            },
+            UserAction::Key(key) => ActionKind::Key {
+                key: key.key.clone(),
+                pressed: key.pressed,
+                mapped: remap.get(&key.key).is_some()
+            }
+        };
+
+        ActionView {
+            offset: Duration::from_micros(action.offset),
+            kind
+        }
+    }
+}
+
+impl Display for ActionKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = |pressed| if pressed { "down" } else { "up" };
+
+        match self {
+            ActionKind::MouseMove { x, y } => write!(f, "Mouse move ({}, {})", x, y),
+            ActionKind::MouseButton { button, pressed } => match utils::button_name(*button) {
+                Some(name) => write!(f, "Mouse {} {}", name, state(*pressed)),
+                None => write!(f, "Mouse Unknown({}) {}", button, state(*pressed))
+            },
+            ActionKind::MouseScroll { delta, horizontal: false } => write!(f, "Scroll {}", delta),
+            ActionKind::MouseScroll { delta, horizontal: true } => write!(f, "Scroll {} horizontally", delta),
+            ActionKind::Key { key, pressed, mapped } => write!(f, "{} {}", label(key, *mapped), state(*pressed))
+        }
+    }
+}
+
+impl Display for ActionView {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {:?}", self.kind, self.offset)
+    }
+}
+
+/// Returns a readable label for a recorded key name, such as
+/// `Left Shift` for `LShift` or `1` for `Key1`.
+///
+/// Keys which have no mapping in `remap`, and so aren't played
+/// back, are labelled `Unknown(<name>)`.
+pub fn key_label(name: &str, remap: &RemapTable) -> String {
+    label(name, remap.get(name).is_some())
+}
+
+/// Returns the label for a key name, given whether it's mapped.
+fn label(name: &str, mapped: bool) -> String {
+    if !mapped {
+        return format!("Unknown({})", name);
+    }
+
+    // Number keys are named after their digit.
+    let name = match name.strip_prefix("Key") {
+        Some(digit) if digit.chars().all(|c| c.is_ascii_digit()) => digit,
+        _ => name
+    };
+
+    // Expand the side of modifier keys, such as `LShift`.
+    let mut chars = name.chars();
+    let (side, rest) = match (chars.next(), chars.next()) {
+        (Some('L'), Some(c)) if c.is_ascii_uppercase() => ("Left ", &name[1..]),
+        (Some('R'), Some(c)) if c.is_ascii_uppercase() => ("Right ", &name[1..]),
+        _ => ("", name)
+    };
+
+    // Split the rest into words, such as `Page Up` and `Numpad 1`.
+    let mut label = side.to_string();
+    let mut previous = None;
+    for c in rest.chars() {
+        if previous.is_some_and(|p: char| p.is_ascii_lowercase())
+            && (c.is_ascii_uppercase() || c.is_ascii_digit()) {
+            label.push(' ');
+        }
+        label.push(c);
+        previous = Some(c);
+    }
+
+    label
+}
+
+impl Macro {
+    /// Returns a snapshot of every recorded action.
+    ///
+    /// The snapshot doesn't hold onto the macro, so it can be
+    /// inspected while the macro is recording or playing back.
+    /// Keys are looked up in the macro's remap table.
+    pub fn actions(&self) -> Vec<ActionView> {
+        utils::lock(&self.actions)
+            .iter()
+            .map(|action| ActionView::new(action, &self.remap))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::macros::{KeyAction, MacroMetadata, MouseButtonAction};
+
+    #[test]
+    fn view_actions() {
+        let actions = vec![
+            MacroAction {
+                offset: 1_000,
+                action: UserAction::Key(KeyAction { key: "A".to_string(), pressed: true })
+            },
+            MacroAction {
+                offset: 2_500,
+                action: UserAction::MouseButton(MouseButtonAction { button: 1, pressed: false })
+            },
+        ];
+        let towa = Macro::from_parts(actions, MacroMetadata::default());
+
+        assert_eq!(towa.actions(), vec![
+            ActionView {
+                offset: Duration::from_millis(1),
+                kind: ActionKind::Key { key: "A".to_string(), pressed: true, mapped: true }
+            },
+            ActionView {
+                offset: Duration::from_micros(2_500),
+                kind: ActionKind::MouseButton { button: 1, pressed: false }
+            },
+        ]);
+
+        // The lock must have been released.
+        assert!(towa.actions.try_lock().is_ok());
+    }
+
+    #[test]
+    fn label_keys() {
+        let remap = RemapTable::new();
+        assert_eq!(key_label("LShift", &remap), "Left Shift");
+        assert_eq!(key_label("RControl", &remap), "Right Control");
+        assert_eq!(key_label("F5", &remap), "F5");
+        assert_eq!(key_label("A", &remap), "A");
+        assert_eq!(key_label("Key1", &remap), "1");
+        assert_eq!(key_label("PageUp", &remap), "Page Up");
+        assert_eq!(key_label("Numpad1", &remap), "Numpad 1");
+        assert_eq!(key_label("NotAKey", &remap), "Unknown(NotAKey)");
+
+        let view = ActionView {
+            offset: Duration::from_millis(12),
+            kind: ActionKind::Key { key: "LShift".to_string(), pressed: true, mapped: true }
+        };
+        assert_eq!(view.to_string(), "Left Shift down at 12ms");
+    }
+
+    #[test]
+    fn label_remapped_keys() {
+        let actions = vec![MacroAction {
+            offset: 1_000,
+            action: UserAction::Key(KeyAction { key: "Power".to_string(), pressed: true })
+        }];
+        let mut towa = Macro::from_parts(actions, MacroMetadata::default());
+        assert_eq!(towa.actions()[0].to_string(), "Unknown(Power) down at 1ms");
+
+        towa.remap_mut().insert("Power", enigo::Key::Unicode('x'));
+        assert_eq!(key_label("Power", towa.remap_mut()), "Power");
+        assert_eq!(towa.actions()[0].to_string(), "Power down at 1ms");
+    }
+}